@@ -0,0 +1,478 @@
+//! Generador de texturas sin ventana, pensado para scripts y pipelines.
+//!
+//! Cada parámetro numérico admite un valor suelto (`8`), una lista (`4,8,12`)
+//! o un rango inclusivo `inicio:fin:paso` (`0.3:0.7:0.1`). Se genera una imagen
+//! por cada combinación. Con un preset de capas las opciones de ruido no
+//! cambian las capas; sólo cuentan el tamaño y `--tileable`. El grafo de nodos
+//! de un preset se ignora: sólo se evalúa desde la aplicación.
+//!
+//! ```text
+//! ruprogen-cli --preset presets/montes.toml --seed 1:10:1 -o "montes_{seed}.png"
+//! ```
+
+use clap::Parser;
+use ruprogen::mask::MaskShape;
+use ruprogen::normalize::NormalizeMode;
+use ruprogen::palette::{Gradient, GradientMode, GradientPreset};
+use ruprogen::preset::Preset;
+use ruprogen::{generate, save_png, FractalKind, NoiseKind, NoiseParams};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+/// Pixels por imagen como mucho: cada uno ocupa 8 bytes en el heightmap y 4 en el PNG.
+const MAX_PIXELS: u64 = 1 << 28;
+/// Valores que puede dar un barrido, e imágenes que puede dar la combinación de todos.
+const MAX_VALUES: usize = 10_000;
+const MAX_COMBOS: usize = 10_000;
+
+#[derive(Parser, Debug)]
+#[command(name = "ruprogen-cli", about = "Genera texturas de ruido y las guarda como PNG")]
+struct Cli {
+    /// Preset TOML (como los de presets/) con los valores base; las opciones lo sobrescriben.
+    #[arg(long)]
+    preset: Option<PathBuf>,
+
+    /// perlin, simplex, opensimplex, value, worley o supersimplex.
+    #[arg(long)]
+    noise: Option<Sweep<NoiseKind>>,
+
+    /// fbm, ridged, billow, turbulence, hybridmulti o heteroterrain.
+    #[arg(long)]
+    fractal: Option<Sweep<FractalKind>>,
+
+    #[arg(long)]
+    octaves: Option<Sweep<u32>>,
+
+    #[arg(long)]
+    lacunarity: Option<Sweep<f64>>,
+
+    #[arg(long)]
+    persistence: Option<Sweep<f64>>,
+
+    #[arg(long)]
+    frequency: Option<Sweep<f64>>,
+
+    #[arg(long)]
+    amplitude: Option<Sweep<f64>>,
+
+    /// Desplazamiento de ridged, hybridmulti y heteroterrain.
+    #[arg(long)]
+    offset: Option<Sweep<f64>>,
+
+    /// Ganancia entre octavas de ridged.
+    #[arg(long)]
+    gain: Option<Sweep<f64>>,
+
+    #[arg(long)]
+    width: Option<Sweep<u32>>,
+
+    #[arg(long)]
+    height: Option<Sweep<u32>>,
+
+    #[arg(long)]
+    seed: Option<Sweep<u32>>,
+
+    /// Genera texturas que se repiten sin costuras.
+    #[arg(long)]
+    tileable: bool,
+
+    /// grayscale, terrain, heat, ocean o viridis. Sustituye al degradado del preset.
+    #[arg(long)]
+    palette: Option<GradientPreset>,
+
+    /// Pinta la paleta en bandas de color plano en lugar de degradado.
+    #[arg(long)]
+    bands: bool,
+
+    /// fixed, minmax, percentile o equalize (minmax si el preset no dice otra cosa).
+    #[arg(long)]
+    normalize: Option<NormalizeMode>,
+
+    /// Rango del modo fixed, como min:max (-1:1 por defecto).
+    #[arg(long, allow_hyphen_values = true)]
+    range: Option<Bounds>,
+
+    /// Percentiles del modo percentile, como bajo:alto (2:98 por defecto).
+    #[arg(long)]
+    percentiles: Option<Bounds>,
+
+    /// Máscara de borde: radial, square, curve o image (con --mask-image).
+    #[arg(long)]
+    mask: Option<MaskShape>,
+
+    /// Imagen en grises para la máscara; implica --mask image.
+    #[arg(long)]
+    mask_image: Option<String>,
+
+    /// Erosión hidráulica con este número de gotas, además de lo que diga el preset.
+    #[arg(long)]
+    erosion: Option<u32>,
+
+    /// Erosión térmica con este número de pasadas, además de lo que diga el preset.
+    #[arg(long)]
+    thermal: Option<u32>,
+
+    /// Ángulo de reposo en grados de la erosión térmica (40 si el preset no dice otra cosa).
+    #[arg(long)]
+    talus: Option<f64>,
+
+    /// Pinta el mapa de biomas del preset en lugar de la paleta y muestra la superficie de cada uno.
+    #[arg(long)]
+    biomes: bool,
+
+    /// Ríos donde desagua al menos esta fracción de la imagen (0.002 es un buen punto de partida).
+    #[arg(long)]
+    rivers: Option<f64>,
+
+    /// Guarda los ríos como SVG en esta ruta, con los mismos marcadores que --output; implica ríos.
+    #[arg(long)]
+    rivers_svg: Option<String>,
+
+    /// Ruta de salida. Admite {index}, {noise}, {fractal}, {seed}, {octaves}, {lacunarity},
+    /// {persistence}, {frequency}, {amplitude}, {offset}, {gain}, {width} y {height}.
+    /// Si le falta el de algún parámetro barrido, se añade el índice a cada imagen.
+    #[arg(short, long, default_value = "ruprogen.png")]
+    output: String,
+}
+
+/// Pareja `a:b` de límites.
+#[derive(Debug, Clone, Copy)]
+struct Bounds(f64, f64);
+
+impl FromStr for Bounds {
+    type Err = String;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let parse = |s: &str| s.trim().parse::<f64>().map_err(|_| format!("valor no válido: {}", s));
+        let (a, b) = arg.split_once(':').ok_or_else(|| format!("se esperaba a:b, no {}", arg))?;
+        let (a, b) = (parse(a)?, parse(b)?);
+        if a >= b {
+            return Err(format!("el límite inferior debe ser menor que el superior: {}", arg));
+        }
+        Ok(Bounds(a, b))
+    }
+}
+
+/// Valores que toma un parámetro a lo largo del barrido.
+#[derive(Debug, Clone)]
+struct Sweep<T>(Vec<T>);
+
+trait SweepValue: Sized + Clone + FromStr {
+    fn range(_start: Self, _end: Self, _step: Self) -> Result<Vec<Self>, String> {
+        Err(String::from("este parámetro no admite rangos"))
+    }
+}
+
+impl SweepValue for u32 {
+    fn range(start: u32, end: u32, step: u32) -> Result<Vec<u32>, String> {
+        if step == 0 {
+            return Err(String::from("el paso del rango debe ser mayor que 0"));
+        }
+        if end < start {
+            return Err(String::from("el final del rango es menor que el inicio"));
+        }
+        if ((end - start) / step) as usize >= MAX_VALUES {
+            return Err(format!("el rango da más de {} valores", MAX_VALUES));
+        }
+        Ok((start..=end).step_by(step as usize).collect())
+    }
+}
+
+impl SweepValue for f64 {
+    fn range(start: f64, end: f64, step: f64) -> Result<Vec<f64>, String> {
+        if step <= 0.0 {
+            return Err(String::from("el paso del rango debe ser mayor que 0"));
+        }
+        // Un pequeño margen para que el extremo no se pierda por redondeo
+        let count = ((end - start) / step + 1e-9).floor();
+        if count < 0.0 {
+            return Err(String::from("el final del rango es menor que el inicio"));
+        }
+        if count >= MAX_VALUES as f64 {
+            return Err(format!("el rango da más de {} valores", MAX_VALUES));
+        }
+        Ok((0..=count as u32).map(|k| start + k as f64 * step).collect())
+    }
+}
+
+impl SweepValue for NoiseKind {}
+impl SweepValue for FractalKind {}
+
+impl<T: SweepValue> FromStr for Sweep<T> {
+    type Err = String;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let parse = |s: &str| s.trim().parse::<T>().map_err(|_| format!("valor no válido: {}", s));
+        let mut values = Vec::new();
+
+        for part in arg.split(',') {
+            match part.split(':').collect::<Vec<_>>().as_slice() {
+                [value] => values.push(parse(value)?),
+                [start, end, step] => values.extend(T::range(parse(start)?, parse(end)?, parse(step)?)?),
+                _ => return Err(format!("se esperaba valor o inicio:fin:paso, no {}", part)),
+            }
+            if values.len() > MAX_VALUES {
+                return Err(format!("el barrido {} da más de {} valores", arg, MAX_VALUES));
+            }
+        }
+
+        if values.is_empty() {
+            return Err(format!("el barrido {} no produce ningún valor", arg));
+        }
+
+        Ok(Sweep(values))
+    }
+}
+
+/// Expande cada barrido sobre todas las combinaciones ya generadas.
+fn expand<T: Clone>(combos: Vec<NoiseParams>, sweep: &Option<Sweep<T>>,
+                    set: impl Fn(&mut NoiseParams, T)) -> Result<Vec<NoiseParams>, String> {
+    let Some(Sweep(values)) = sweep else { return Ok(combos) };
+    if combos.len() * values.len() > MAX_COMBOS {
+        return Err(format!("el barrido da más de {} imágenes", MAX_COMBOS));
+    }
+
+    Ok(combos.into_iter()
+        .flat_map(|base| values.iter().map(move |value| (base.clone(), value.clone())))
+        .map(|(mut params, value)| {
+            set(&mut params, value);
+            params
+        })
+        .collect())
+}
+
+fn output_path(template: &str, index: usize, params: &NoiseParams) -> String {
+    template
+        .replace("{index}", &index.to_string())
+        .replace("{noise}", &format!("{:?}", params.noise).to_lowercase())
+        .replace("{fractal}", &format!("{:?}", params.fractal).to_lowercase())
+        .replace("{seed}", &params.seed.to_string())
+        .replace("{octaves}", &params.octaves.to_string())
+        .replace("{lacunarity}", &params.lacunarity.to_string())
+        .replace("{persistence}", &params.persistence.to_string())
+        .replace("{frequency}", &params.frequency.to_string())
+        .replace("{amplitude}", &params.amplitude.to_string())
+        .replace("{offset}", &params.offset.to_string())
+        .replace("{gain}", &params.gain.to_string())
+        .replace("{width}", &params.width.to_string())
+        .replace("{height}", &params.height.to_string())
+}
+
+/// Rutas de todas las combinaciones. Si a la plantilla le falta el marcador de
+/// algún parámetro barrido, varias acabarían en el mismo fichero; entonces se
+/// añade el índice a todas.
+fn output_paths(template: &str, combos: &[NoiseParams]) -> Vec<String> {
+    let paths: Vec<String> = combos.iter().enumerate()
+        .map(|(index, params)| output_path(template, index, params))
+        .collect();
+    if paths.iter().collect::<HashSet<_>>().len() == paths.len() {
+        return paths;
+    }
+
+    paths.into_iter().enumerate()
+        .map(|(index, path)| match path.rsplit_once('.') {
+            Some((stem, ext)) => format!("{}_{}.{}", stem, index, ext),
+            None => format!("{}_{}", path, index),
+        })
+        .collect()
+}
+
+fn run(cli: Cli) -> Result<(), String> {
+    let preset = match &cli.preset {
+        Some(path) => Preset::load(path)?,
+        None => Preset::default(),
+    };
+
+    let mut base = preset.params;
+    base.tileable |= cli.tileable;
+
+    let mut combos = vec![base];
+    combos = expand(combos, &cli.noise, |p, v| p.noise = v)?;
+    combos = expand(combos, &cli.fractal, |p, v| p.fractal = v)?;
+    combos = expand(combos, &cli.octaves, |p, v| p.octaves = v)?;
+    combos = expand(combos, &cli.lacunarity, |p, v| p.lacunarity = v)?;
+    combos = expand(combos, &cli.persistence, |p, v| p.persistence = v)?;
+    combos = expand(combos, &cli.frequency, |p, v| p.frequency = v)?;
+    combos = expand(combos, &cli.amplitude, |p, v| p.amplitude = v)?;
+    combos = expand(combos, &cli.offset, |p, v| p.offset = v)?;
+    combos = expand(combos, &cli.gain, |p, v| p.gain = v)?;
+    combos = expand(combos, &cli.width, |p, v| p.width = v)?;
+    combos = expand(combos, &cli.height, |p, v| p.height = v)?;
+    combos = expand(combos, &cli.seed, |p, v| p.seed = v)?;
+
+    let mut gradient = match cli.palette {
+        Some(palette) => Gradient::preset(palette),
+        None => preset.gradient,
+    };
+    if cli.bands {
+        gradient.mode = GradientMode::Bands;
+    }
+
+    let mut normalization = preset.normalize;
+    if let Some(mode) = cli.normalize {
+        normalization.mode = mode;
+    }
+    if let Some(Bounds(min, max)) = cli.range {
+        (normalization.min, normalization.max) = (min, max);
+    }
+    if let Some(Bounds(low, high)) = cli.percentiles {
+        (normalization.low, normalization.high) = (low, high);
+    }
+
+    let mut post = preset.post;
+    if let Some(shape) = cli.mask {
+        post.mask.enabled = true;
+        post.mask.shape = shape;
+    }
+    if let Some(path) = cli.mask_image {
+        post.mask.enabled = true;
+        post.mask.shape = MaskShape::Image;
+        post.mask.image = path;
+    }
+    if let Some(droplets) = cli.erosion {
+        post.hydraulic.enabled = true;
+        post.hydraulic.droplets = droplets;
+    }
+    if let Some(iterations) = cli.thermal {
+        post.thermal.enabled = true;
+        post.thermal.iterations = iterations;
+    }
+    if let Some(talus) = cli.talus {
+        post.thermal.talus = talus;
+    }
+
+    let mut rivers = preset.rivers;
+    if let Some(threshold) = cli.rivers {
+        rivers.enabled = true;
+        rivers.threshold = threshold;
+    }
+    rivers.enabled |= cli.rivers_svg.is_some();
+
+    let mut biomes = preset.biomes;
+    biomes.enabled |= cli.biomes;
+    if biomes.enabled {
+        biomes.validate()?;
+    }
+
+    if let Some(params) = combos.iter()
+        .find(|p| p.width == 0 || p.height == 0 || p.width as u64 * p.height as u64 > MAX_PIXELS) {
+        return Err(format!("tamaño no válido: {}x{}", params.width, params.height));
+    }
+
+    let total = combos.len();
+    let paths = output_paths(&cli.output, &combos);
+    let svg_paths = cli.rivers_svg.as_deref().map(|template| output_paths(template, &combos));
+    for (index, (params, path)) in combos.iter().zip(paths).enumerate() {
+        let mut heightmap = generate(params);
+        post.apply(&mut heightmap, params.seed, &AtomicBool::new(false), &|_| {})?;
+        let network = rivers.enabled.then(|| rivers.apply(&mut heightmap));
+        let normalizer = normalization.fit(&heightmap.data);
+        let (pixels, areas) = if biomes.enabled {
+            let moisture = biomes.moisture.generate(params, &AtomicBool::new(false)).expect("una generación sin cancelar siempre termina");
+            let map = biomes.classify(&heightmap, &normalizer, &moisture)?;
+            (map.to_rgba(&biomes), map.areas(biomes.biomes.len()))
+        } else {
+            (heightmap.to_rgba(&gradient, &normalizer), Vec::new())
+        };
+        save_png(&path, heightmap.width, heightmap.height, &pixels)
+            .map_err(|error| format!("no se pudo guardar {}: {}", path, error))?;
+        println!("[{}/{}] {}", index + 1, total, path);
+        if let Some(network) = network {
+            println!("    {} ríos", network.rivers.len());
+            if let Some(svg_paths) = &svg_paths {
+                let svg = &svg_paths[index];
+                network.save_svg(svg)?;
+                println!("    {}", svg);
+            }
+        }
+        for (biome, area) in biomes.biomes.iter().zip(areas).filter(|(_, area)| *area > 0.0) {
+            println!("    {}: {:.1}%", biome.name, area * 100.0);
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values<T: SweepValue>(arg: &str) -> Result<Vec<T>, String> {
+        arg.parse::<Sweep<T>>().map(|Sweep(values)| values)
+    }
+
+    #[test]
+    fn sweep_parses_values_lists_and_ranges() {
+        assert_eq!(values::<u32>("8").unwrap(), [8]);
+        assert_eq!(values::<u32>("4,8,12").unwrap(), [4, 8, 12]);
+        assert_eq!(values::<u32>("1:10:3").unwrap(), [1, 4, 7, 10]);
+        assert_eq!(values::<u32>("1,5:6:1").unwrap(), [1, 5, 6]);
+        let floats = values::<f64>("0.3:0.7:0.1").unwrap();
+        assert_eq!(floats.len(), 5);
+        assert!((floats[4] - 0.7).abs() < 1e-9);
+        assert_eq!(values::<NoiseKind>("perlin,worley").unwrap(), [NoiseKind::Perlin, NoiseKind::Worley]);
+    }
+
+    #[test]
+    fn sweep_rejects_bad_input() {
+        assert!(values::<u32>("1:10:0").is_err());
+        assert!(values::<f64>("1:0:0.1").is_err());
+        assert!(values::<u32>("1:2").is_err());
+        assert!(values::<u32>("x").is_err());
+        assert!(values::<NoiseKind>("perlin:worley:1").is_err());
+        // Al revés, los dos tipos fallan igual
+        assert_eq!(values::<u32>("5:1:1").unwrap_err(), "el final del rango es menor que el inicio");
+        assert_eq!(values::<f64>("5:1:1").unwrap_err(), "el final del rango es menor que el inicio");
+    }
+
+    #[test]
+    fn sweeps_are_capped() {
+        assert!(values::<u32>("0:4294967295:1").is_err());
+        assert!(values::<f64>("0:1:0.00000001").is_err());
+        assert_eq!(values::<u32>("1:10000:1").unwrap().len(), MAX_VALUES);
+
+        let seeds = Some(Sweep((0..1000).collect::<Vec<u32>>()));
+        let combos = expand(vec![NoiseParams::default()], &seeds, |p, v| p.seed = v).unwrap();
+        assert!(expand(combos, &seeds, |p, v| p.octaves = v).is_err());
+    }
+
+    #[test]
+    fn expand_builds_every_combination() {
+        let combos = expand(vec![NoiseParams::default()], &Some(Sweep(vec![1u32, 2])), |p, v| p.seed = v).unwrap();
+        let combos = expand(combos, &Some(Sweep(vec![4u32, 8, 12])), |p, v| p.octaves = v).unwrap();
+        assert_eq!(combos.len(), 6);
+        assert_eq!(combos.iter().map(|p| (p.seed, p.octaves)).collect::<Vec<_>>(),
+                   [(1, 4), (1, 8), (1, 12), (2, 4), (2, 8), (2, 12)]);
+    }
+
+    #[test]
+    fn output_paths_never_collide() {
+        let octaves = Some(Sweep(vec![4u32, 6, 8]));
+        let combos = expand(vec![NoiseParams::default()], &octaves, |p, v| p.octaves = v).unwrap();
+        // Falta {octaves}: se numeran todas
+        assert_eq!(output_paths("out_{seed}.png", &combos), ["out_0_0.png", "out_0_1.png", "out_0_2.png"]);
+        assert_eq!(output_paths("out", &combos), ["out_0", "out_1", "out_2"]);
+        // Con el marcador del parámetro barrido se respetan tal cual
+        assert_eq!(output_paths("out_{octaves}.png", &combos), ["out_4.png", "out_6.png", "out_8.png"]);
+        assert_eq!(output_paths("out.png", &combos[..1]), ["out.png"]);
+    }
+
+    #[test]
+    fn huge_sizes_are_rejected() {
+        let cli = Cli::parse_from(["ruprogen-cli", "--width", "70000", "--height", "70000"]);
+        assert!(run(cli).is_err());
+    }
+}
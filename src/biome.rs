@@ -0,0 +1,293 @@
+//! Mapa de biomas a partir de la altura, la humedad y la temperatura.
+//!
+//! Como en el diagrama de Whittaker, una tabla de temperatura por humedad da
+//! el bioma de cada pixel; por debajo del nivel del agua todo es agua. La
+//! humedad es otro campo de ruido, independiente de la altura.
+
+use crate::normalize::{Normalization, Normalizer};
+use crate::{fbm, sample_octave, Heightmap, NoiseParams};
+use noise::Perlin;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Biome {
+    pub name: String,
+    /// En los presets se escribe como `"#RRGGBB"`.
+    #[serde(with = "crate::palette::hex_color")]
+    pub color: [u8; 3],
+}
+
+impl Biome {
+    pub fn new(name: &str, color: [u8; 3]) -> Self {
+        Biome { name: name.to_string(), color }
+    }
+}
+
+/// Campo de humedad: Perlin fBm sobre la misma zona que la imagen.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MoistureField {
+    /// Se suma a la semilla de la imagen, para que cambien juntas sin coincidir.
+    pub seed_offset: u32,
+    pub frequency: f64,
+    pub octaves: u32,
+}
+
+impl Default for MoistureField {
+    fn default() -> Self {
+        MoistureField { seed_offset: 1, frequency: 1.5, octaves: 6 }
+    }
+}
+
+impl MoistureField {
+    /// Humedad cruda (-1..1 más o menos) con el tamaño y la zona de `params`;
+    /// se repite como la imagen si es `tileable`. Devuelve `None` si se cancela.
+    pub fn generate(&self, params: &NoiseParams, cancel: &AtomicBool) -> Option<Heightmap> {
+        let source = Perlin::new(params.seed.wrapping_add(self.seed_offset));
+        let mut data = vec![0.0; params.width as usize * params.height as usize];
+
+        if !data.is_empty() {
+            data.par_chunks_mut(params.width as usize)
+                .enumerate()
+                .try_for_each(|(j, row)| {
+                    if cancel.load(Ordering::Relaxed) {
+                        return None;
+                    }
+                    let v = j as f64 / params.height as f64;
+                    for (i, value) in row.iter_mut().enumerate() {
+                        let pos = params.region.point(i as f64 / params.width as f64, v);
+                        let octave = |f| sample_octave(&source, pos, f, params.tileable);
+                        *value = fbm(&octave, self.octaves, 2.0, 0.5, self.frequency, 1.0);
+                    }
+                    Some(())
+                })?;
+        }
+
+        Some(Heightmap { width: params.width, height: params.height, data })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BiomeSettings {
+    pub enabled: bool,
+    pub moisture: MoistureField,
+    /// Temperatura según la fila: polos arriba y abajo, ecuador en el centro.
+    pub latitude: bool,
+    /// Temperatura en la costa, en 0..1, cuando no se usa la latitud.
+    pub temperature: f64,
+    /// Lo que baja la temperatura de la costa a la cima más alta.
+    pub lapse: f64,
+    /// Altura normalizada (0..1) por debajo de la cual hay agua.
+    pub water_level: f64,
+    pub biomes: Vec<Biome>,
+    /// Nombre del bioma del agua.
+    pub water: String,
+    /// Filas de frío a calor y columnas de seco a húmedo, con nombres de
+    /// `biomes`. Las bandas son iguales.
+    pub table: Vec<Vec<String>>,
+}
+
+impl Default for BiomeSettings {
+    fn default() -> Self {
+        let row = |names: [&str; 4]| names.map(String::from).to_vec();
+        BiomeSettings {
+            enabled: false,
+            moisture: MoistureField::default(),
+            latitude: true,
+            temperature: 0.7,
+            lapse: 0.6,
+            water_level: 0.4,
+            biomes: vec![
+                Biome::new("Agua", [48, 84, 150]),
+                Biome::new("Nieve", [240, 244, 248]),
+                Biome::new("Tundra", [160, 170, 150]),
+                Biome::new("Desierto frío", [180, 168, 140]),
+                Biome::new("Taiga", [70, 110, 80]),
+                Biome::new("Pradera", [150, 180, 90]),
+                Biome::new("Bosque templado", [60, 130, 60]),
+                Biome::new("Bosque lluvioso", [30, 100, 70]),
+                Biome::new("Desierto", [225, 200, 130]),
+                Biome::new("Sabana", [190, 180, 80]),
+                Biome::new("Bosque tropical", [80, 150, 50]),
+                Biome::new("Selva", [20, 110, 40]),
+            ],
+            water: String::from("Agua"),
+            table: vec![
+                row(["Tundra", "Tundra", "Nieve", "Nieve"]),
+                row(["Desierto frío", "Pradera", "Taiga", "Taiga"]),
+                row(["Desierto", "Pradera", "Bosque templado", "Bosque lluvioso"]),
+                row(["Desierto", "Sabana", "Bosque tropical", "Selva"]),
+            ],
+        }
+    }
+}
+
+/// Bioma de cada pixel, como índice en `BiomeSettings::biomes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeMap {
+    pub width: u32,
+    pub height: u32,
+    pub biomes: Vec<usize>,
+}
+
+impl BiomeSettings {
+    // Índices de la tabla y del agua; falla si nombran un bioma que no existe
+    fn lookup(&self) -> Result<(Vec<Vec<usize>>, usize), String> {
+        let index = |name: &String| {
+            self.biomes.iter()
+                .position(|biome| &biome.name == name)
+                .ok_or_else(|| format!("la tabla de biomas nombra uno que no existe: {}", name))
+        };
+        let table = self.table.iter()
+            .map(|row| row.iter().map(index).collect::<Result<Vec<_>, _>>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if table.is_empty() || table.iter().any(Vec::is_empty) {
+            return Err(String::from("la tabla de biomas no puede tener filas vacías"));
+        }
+        Ok((table, index(&self.water)?))
+    }
+
+    /// Comprueba que la tabla y el agua nombran biomas de la lista.
+    pub fn validate(&self) -> Result<(), String> {
+        self.lookup().map(|_| ())
+    }
+
+    /// Clasifica cada pixel. La altura se pasa a 0..1 con `normalizer`, el
+    /// mismo que colorea la imagen; la humedad se estira a 0..1 entera.
+    pub fn classify(&self, elevation: &Heightmap, normalizer: &Normalizer, moisture: &Heightmap)
+        -> Result<BiomeMap, String> {
+        let (table, water) = self.lookup()?;
+        let moisture_normalizer = Normalization::default().fit(&moisture.data);
+        let (width, height) = (elevation.width, elevation.height);
+
+        let biomes = elevation.data.iter().zip(&moisture.data).enumerate()
+            .map(|(k, (&e, &m))| {
+                let e = normalizer.apply(e);
+                if e < self.water_level {
+                    return water;
+                }
+                let base = if self.latitude {
+                    let v = (k as u32 / width) as f64 / height.max(1) as f64;
+                    1.0 - (v * 2.0 - 1.0).abs()
+                } else {
+                    self.temperature
+                };
+                let altitude = (e - self.water_level) / (1.0 - self.water_level).max(f64::EPSILON);
+                let temperature = (base - self.lapse * altitude).clamp(0.0, 1.0);
+
+                let row = band(temperature, table.len());
+                table[row][band(moisture_normalizer.apply(m), table[row].len())]
+            })
+            .collect();
+
+        Ok(BiomeMap { width, height, biomes })
+    }
+}
+
+// Banda de `count` iguales en 0..1 en la que cae `t`
+fn band(t: f64, count: usize) -> usize {
+    ((t * count as f64) as usize).min(count - 1)
+}
+
+impl BiomeMap {
+    pub fn to_rgba(&self, settings: &BiomeSettings) -> Vec<u8> {
+        self.biomes.iter()
+            .flat_map(|&index| {
+                let [r, g, b] = settings.biomes[index].color;
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+
+    /// Fracción (0..1) de la imagen que ocupa cada bioma de la lista.
+    pub fn areas(&self, count: usize) -> Vec<f64> {
+        let mut areas = vec![0.0; count];
+        for &index in &self.biomes {
+            areas[index] += 1.0;
+        }
+        let total = self.biomes.len().max(1) as f64;
+        areas.iter_mut().for_each(|area| *area /= total);
+        areas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Region;
+
+    fn row(data: &[f64]) -> Heightmap {
+        Heightmap { width: data.len() as u32, height: 1, data: data.to_vec() }
+    }
+
+    fn names<'a>(settings: &'a BiomeSettings, map: &BiomeMap) -> Vec<&'a str> {
+        map.biomes.iter().map(|&index| settings.biomes[index].name.as_str()).collect()
+    }
+
+    #[test]
+    fn classify_follows_the_table() {
+        // Temperatura fija en la tercera fila: desierto, pradera, bosque templado, bosque lluvioso
+        let settings = BiomeSettings { latitude: false, temperature: 0.6, lapse: 0.0, ..BiomeSettings::default() };
+        let elevation = row(&[0.2, 0.39, 0.4, 0.7, 0.7, 1.0]);
+        let moisture = row(&[0.0, 1.0, 0.0, 0.3, 0.6, 1.0]);
+        let normalizer = Normalizer::Linear { min: 0.0, max: 1.0 };
+
+        let map = settings.classify(&elevation, &normalizer, &moisture).unwrap();
+        assert_eq!(names(&settings, &map),
+                   ["Agua", "Agua", "Desierto", "Pradera", "Bosque templado", "Bosque lluvioso"]);
+    }
+
+    #[test]
+    fn classify_cools_with_altitude() {
+        let settings = BiomeSettings { latitude: false, temperature: 0.9, lapse: 0.9, ..BiomeSettings::default() };
+        // El tercero, bajo el agua, sólo está para que la humedad no sea plana
+        let elevation = row(&[0.4, 1.0, 0.0]);
+        let moisture = row(&[0.0, 0.0, 1.0]);
+        let map = settings.classify(&elevation, &Normalizer::Linear { min: 0.0, max: 1.0 }, &moisture).unwrap();
+        // En la costa hace calor; en la cima, frío
+        assert_eq!(names(&settings, &map), ["Desierto", "Tundra", "Agua"]);
+    }
+
+    #[test]
+    fn areas_add_up_to_one() {
+        let settings = BiomeSettings::default();
+        let elevation = row(&[0.1, 0.5, 0.6, 0.8, 0.9, 0.95, 0.3]);
+        let moisture = row(&[0.2, 0.9, 0.1, 0.5, 0.4, 0.7, 0.0]);
+        let normalizer = Normalization::default().fit(&elevation.data);
+        let map = settings.classify(&elevation, &normalizer, &moisture).unwrap();
+
+        let areas = map.areas(settings.biomes.len());
+        assert!((areas.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn validate_checks_names() {
+        assert!(BiomeSettings::default().validate().is_ok());
+
+        let mut settings = BiomeSettings::default();
+        settings.table[1][2] = String::from("Marisma");
+        assert!(settings.validate().is_err());
+
+        let settings = BiomeSettings { water: String::from("Lava"), ..BiomeSettings::default() };
+        assert!(settings.validate().is_err());
+
+        let settings = BiomeSettings { table: vec![Vec::new()], ..BiomeSettings::default() };
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn tileable_moisture_repeats_like_the_image() {
+        let cancel = AtomicBool::new(false);
+        let field = MoistureField::default();
+        let params = NoiseParams { width: 16, height: 16, tileable: true, ..NoiseParams::default() };
+        // Una tesela más a la derecha tiene que dar lo mismo
+        let shifted = NoiseParams { region: Region { x: 1.0, ..Region::default() }, ..params.clone() };
+
+        let a = field.generate(&params, &cancel).unwrap();
+        let b = field.generate(&shifted, &cancel).unwrap();
+        assert!(a.data.iter().zip(&b.data).all(|(a, b)| (a - b).abs() < 1e-9));
+    }
+}
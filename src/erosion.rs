@@ -0,0 +1,387 @@
+//! Erosión sobre un heightmap ya generado, para que el ruido parezca terreno.
+//!
+//! Las alturas son los valores crudos (-1..1 más o menos) y las distancias
+//! se miden en pixels de la imagen. Para hablar de pendientes, el rango -1..1
+//! ocupa en vertical lo mismo que el ancho de la imagen.
+
+use crate::Heightmap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Erosión hidráulica por gotas: cada gota baja por la pendiente arrancando
+/// material donde va deprisa y soltándolo donde se frena o se evapora.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HydraulicErosion {
+    pub enabled: bool,
+    /// Gotas a resolución completa; las vistas previas usan menos.
+    pub droplets: u32,
+    /// 0: la gota sigue la pendiente; 1: mantiene su dirección.
+    pub inertia: f64,
+    /// Sedimento que puede llevar una gota según su velocidad y su agua.
+    pub capacity: f64,
+    /// Fracción del exceso de sedimento que se suelta en cada paso.
+    pub deposition: f64,
+    /// Fracción de la capacidad libre que se arranca en cada paso.
+    pub erosion: f64,
+    /// Fracción del agua que se pierde en cada paso.
+    pub evaporation: f64,
+    /// Radio en pixels en el que se reparte lo que se arranca.
+    pub radius: u32,
+}
+
+impl Default for HydraulicErosion {
+    fn default() -> Self {
+        HydraulicErosion {
+            enabled: false,
+            droplets: 200_000,
+            inertia: 0.05,
+            capacity: 4.0,
+            deposition: 0.3,
+            erosion: 0.3,
+            evaporation: 0.01,
+            radius: 3,
+        }
+    }
+}
+
+// Constantes del modelo que no merece la pena exponer
+const GRAVITY: f64 = 4.0;
+const MAX_STEPS: u32 = 30;
+const MIN_CAPACITY: f64 = 0.0001;
+
+impl HydraulicErosion {
+    /// La misma erosión para una imagen `factor` veces más pequeña en cada eje.
+    pub fn scaled(&self, factor: f64) -> HydraulicErosion {
+        HydraulicErosion {
+            droplets: (self.droplets as f64 * factor * factor).round() as u32,
+            radius: ((self.radius as f64 * factor).round() as u32).max(1),
+            ..*self
+        }
+    }
+
+    /// Deja caer las gotas, en posiciones que sólo dependen de `seed`. Llama a
+    /// `progress` con la fracción hecha y devuelve `false` si se cancela.
+    pub fn apply(&self, heightmap: &mut Heightmap, seed: u32, cancel: &AtomicBool, progress: &dyn Fn(f32)) -> bool {
+        let (width, height) = (heightmap.width as usize, heightmap.height as usize);
+        if width < 3 || height < 3 {
+            return true;
+        }
+
+        let brush = brush(self.radius);
+        let map = &mut heightmap.data;
+        let mut rng = StdRng::seed_from_u64(seed as u64);
+        let report_every = (self.droplets / 100).max(1);
+
+        for droplet in 0..self.droplets {
+            if droplet % report_every == 0 {
+                if cancel.load(Ordering::Relaxed) {
+                    return false;
+                }
+                progress(droplet as f32 / self.droplets as f32);
+            }
+
+            let pos = [rng.gen_range(0.0..(width - 1) as f64), rng.gen_range(0.0..(height - 1) as f64)];
+            self.droplet(map, [width, height], &brush, pos);
+        }
+
+        progress(1.0);
+        true
+    }
+
+    // Una gota desde `pos` hasta que se para o sale del mapa. Devuelve el
+    // sedimento que aún lleva, que se pierde con ella.
+    fn droplet(&self, map: &mut [f64], [width, height]: [usize; 2], brush: &[(i64, i64, f64)], mut pos: [f64; 2]) -> f64 {
+        let mut dir = [0.0, 0.0];
+        let (mut speed, mut water, mut sediment) = (1.0, 1.0, 0.0);
+
+        for _ in 0..MAX_STEPS {
+            let (cell_x, cell_y) = (pos[0] as usize, pos[1] as usize);
+            let offset = [pos[0].fract(), pos[1].fract()];
+            let (old_height, gradient) = height_and_gradient(map, width, pos);
+
+            // La nueva dirección mezcla la anterior con la bajada más rápida
+            dir = [
+                dir[0] * self.inertia - gradient[0] * (1.0 - self.inertia),
+                dir[1] * self.inertia - gradient[1] * (1.0 - self.inertia),
+            ];
+            let length = (dir[0] * dir[0] + dir[1] * dir[1]).sqrt();
+            if length == 0.0 {
+                break; // llano: la gota se queda donde está
+            }
+            dir = [dir[0] / length, dir[1] / length];
+            pos = [pos[0] + dir[0], pos[1] + dir[1]];
+            if pos[0] < 0.0 || pos[1] < 0.0 || pos[0] >= (width - 1) as f64 || pos[1] >= (height - 1) as f64 {
+                break;
+            }
+
+            let delta = height_and_gradient(map, width, pos).0 - old_height;
+            let capacity = (-delta * speed * water * self.capacity).max(MIN_CAPACITY);
+
+            if sediment > capacity || delta > 0.0 {
+                // Subiendo rellena el hoyo del que sale; si no, suelta el exceso
+                let amount = if delta > 0.0 { delta.min(sediment) } else { (sediment - capacity) * self.deposition };
+                sediment -= amount;
+                deposit(map, width, [cell_x, cell_y], offset, amount);
+            } else {
+                let amount = ((capacity - sediment) * self.erosion).min(-delta);
+                // Cerca del borde parte del pincel cae fuera: sólo se carga lo que se quita
+                let mut removed = 0.0;
+                for &(dx, dy, weight) in brush {
+                    let (x, y) = (cell_x as i64 + dx, cell_y as i64 + dy);
+                    if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                        map[y as usize * width + x as usize] -= amount * weight;
+                        removed += amount * weight;
+                    }
+                }
+                sediment += removed;
+            }
+
+            speed = (speed * speed - delta * GRAVITY).max(0.0).sqrt();
+            water *= 1.0 - self.evaporation;
+        }
+
+        sediment
+    }
+}
+
+/// Erosión térmica: donde la pendiente pasa del ángulo de reposo (talud), el
+/// material cae a los vecinos más bajos hasta que deja de pasarlo.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThermalErosion {
+    pub enabled: bool,
+    /// Pasadas a resolución completa; las vistas previas usan menos.
+    pub iterations: u32,
+    /// Ángulo de reposo, en grados.
+    pub talus: f64,
+    /// Fracción del exceso que se mueve en cada pasada (hasta 0.5).
+    pub strength: f64,
+}
+
+impl Default for ThermalErosion {
+    fn default() -> Self {
+        ThermalErosion { enabled: false, iterations: 50, talus: 40.0, strength: 0.25 }
+    }
+}
+
+// Vecinos con su distancia, para que el talud no dependa de la dirección
+const NEIGHBOURS: [(i64, i64, f64); 8] = [
+    (-1, -1, std::f64::consts::SQRT_2), (0, -1, 1.0), (1, -1, std::f64::consts::SQRT_2),
+    (-1, 0, 1.0), (1, 0, 1.0),
+    (-1, 1, std::f64::consts::SQRT_2), (0, 1, 1.0), (1, 1, std::f64::consts::SQRT_2),
+];
+
+impl ThermalErosion {
+    /// La misma erosión para una imagen `factor` veces más pequeña en cada eje:
+    /// el material recorre los mismos pixels por pasada, así que hacen falta menos.
+    pub fn scaled(&self, factor: f64) -> ThermalErosion {
+        ThermalErosion { iterations: ((self.iterations as f64 * factor).round() as u32).max(1), ..*self }
+    }
+
+    /// Llama a `progress` con la fracción hecha y devuelve `false` si se cancela.
+    pub fn apply(&self, heightmap: &mut Heightmap, cancel: &AtomicBool, progress: &dyn Fn(f32)) -> bool {
+        let (width, height) = (heightmap.width as usize, heightmap.height as usize);
+        // Desnivel máximo por pixel de distancia antes de que el material caiga
+        let limit = self.talus.clamp(0.0, 89.9).to_radians().tan() * 2.0 / width.max(1) as f64;
+        let strength = self.strength.clamp(0.0, 0.5);
+        let map = &mut heightmap.data;
+        // Los cambios de cada pasada se aplican al final, para no depender del orden
+        let mut change = vec![0.0; map.len()];
+
+        for iteration in 0..self.iterations {
+            if cancel.load(Ordering::Relaxed) {
+                return false;
+            }
+            progress(iteration as f32 / self.iterations as f32);
+            change.fill(0.0);
+
+            for y in 0..height {
+                // Una pasada sobre un mapa grande tarda: se mira también por filas
+                if cancel.load(Ordering::Relaxed) {
+                    return false;
+                }
+                for x in 0..width {
+                    let here = map[y * width + x];
+                    let mut excess = [0.0; 8];
+                    for (k, &(dx, dy, distance)) in NEIGHBOURS.iter().enumerate() {
+                        let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                        if nx >= 0 && ny >= 0 && (nx as usize) < width && (ny as usize) < height {
+                            excess[k] = (here - map[ny as usize * width + nx as usize] - limit * distance).max(0.0);
+                        }
+                    }
+                    let total: f64 = excess.iter().sum();
+                    if total == 0.0 {
+                        continue;
+                    }
+
+                    // Se mueve una parte del mayor exceso, repartida según el de cada vecino
+                    let moved = strength * excess.iter().copied().fold(0.0, f64::max);
+                    change[y * width + x] -= moved;
+                    for (k, &(dx, dy, _)) in NEIGHBOURS.iter().enumerate() {
+                        if excess[k] > 0.0 {
+                            let index = (y as i64 + dy) as usize * width + (x as i64 + dx) as usize;
+                            change[index] += moved * excess[k] / total;
+                        }
+                    }
+                }
+            }
+
+            for (value, change) in map.iter_mut().zip(&change) {
+                *value += change;
+            }
+        }
+
+        progress(1.0);
+        true
+    }
+}
+
+// Reparte `amount` entre las cuatro esquinas de la celda según la posición dentro de ella
+fn deposit(map: &mut [f64], width: usize, [x, y]: [usize; 2], [u, v]: [f64; 2], amount: f64) {
+    let index = y * width + x;
+    map[index] += amount * (1.0 - u) * (1.0 - v);
+    map[index + 1] += amount * u * (1.0 - v);
+    map[index + width] += amount * (1.0 - u) * v;
+    map[index + width + 1] += amount * u * v;
+}
+
+// Altura interpolada y pendiente en una posición dentro del mapa
+fn height_and_gradient(map: &[f64], width: usize, [px, py]: [f64; 2]) -> (f64, [f64; 2]) {
+    let (x, y) = (px as usize, py as usize);
+    let (u, v) = (px - x as f64, py - y as f64);
+    let index = y * width + x;
+    let (nw, ne, sw, se) = (map[index], map[index + 1], map[index + width], map[index + width + 1]);
+
+    let gradient = [(ne - nw) * (1.0 - v) + (se - sw) * v, (sw - nw) * (1.0 - u) + (se - ne) * u];
+    let height = nw * (1.0 - u) * (1.0 - v) + ne * u * (1.0 - v) + sw * (1.0 - u) * v + se * u * v;
+    (height, gradient)
+}
+
+// Pesos de las celdas a menos de `radius`, más cuanto más cerca, que suman 1
+fn brush(radius: u32) -> Vec<(i64, i64, f64)> {
+    let r = radius.max(1) as i64;
+    let mut cells: Vec<(i64, i64, f64)> = (-r..=r)
+        .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+        .filter_map(|(dx, dy)| {
+            let weight = r as f64 - ((dx * dx + dy * dy) as f64).sqrt();
+            (weight > 0.0).then_some((dx, dy, weight))
+        })
+        .collect();
+    let total: f64 = cells.iter().map(|cell| cell.2).sum();
+    for cell in &mut cells {
+        cell.2 /= total;
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate, NoiseParams};
+
+    fn terrain(size: u32) -> Heightmap {
+        generate(&NoiseParams { width: size, height: size, seed: 7, ..NoiseParams::default() })
+    }
+
+    // Plano inclinado que baja hacia el borde que diga `dir`
+    fn slope(size: u32, dir: [f64; 2]) -> Heightmap {
+        let data = (0..size * size)
+            .map(|k| ((k % size) as f64 * dir[0] + (k / size) as f64 * dir[1]) * -0.05)
+            .collect();
+        Heightmap { width: size, height: size, data }
+    }
+
+    fn total(heightmap: &Heightmap) -> f64 {
+        heightmap.data.iter().sum()
+    }
+
+    #[test]
+    fn brush_weights_add_up_to_one() {
+        for radius in 0..6 {
+            let sum: f64 = brush(radius).iter().map(|cell| cell.2).sum();
+            assert!((sum - 1.0).abs() < 1e-12, "radio {}", radius);
+        }
+    }
+
+    #[test]
+    fn hydraulic_is_deterministic_for_a_seed() {
+        let erosion = HydraulicErosion { droplets: 2000, ..HydraulicErosion::default() };
+        let (mut a, mut b) = (terrain(32), terrain(32));
+        assert!(erosion.apply(&mut a, 3, &AtomicBool::new(false), &|_| {}));
+        assert!(erosion.apply(&mut b, 3, &AtomicBool::new(false), &|_| {}));
+        assert_eq!(a, b);
+        assert_ne!(a, terrain(32));
+    }
+
+    #[test]
+    fn hydraulic_never_creates_material() {
+        // Con un mapa tan pequeño el pincel se sale a menudo por los bordes
+        let erosion = HydraulicErosion { radius: 4, ..HydraulicErosion::default() };
+        let mut heightmap = terrain(12);
+        let brush = brush(erosion.radius);
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..2000 {
+            let before = total(&heightmap);
+            let pos = [rng.gen_range(0.0..11.0), rng.gen_range(0.0..11.0)];
+            let carried = erosion.droplet(&mut heightmap.data, [12, 12], &brush, pos);
+            assert!(carried >= 0.0);
+            assert!(total(&heightmap) + carried <= before + 1e-12);
+        }
+    }
+
+    #[test]
+    fn droplets_leaving_the_map_do_not_deposit() {
+        let erosion = HydraulicErosion { droplets: 500, ..HydraulicErosion::default() };
+        for dir in [[1.0, 0.0], [-1.0, 0.0], [0.0, 1.0], [0.0, -1.0]] {
+            let original = slope(16, dir);
+            let mut heightmap = original.clone();
+            erosion.apply(&mut heightmap, 5, &AtomicBool::new(false), &|_| {});
+            // Todo lo arrancado se va con las gotas por el borde
+            assert!(heightmap.data.iter().zip(&original.data).all(|(after, before)| after <= before));
+            assert!(total(&heightmap) < total(&original));
+        }
+    }
+
+    #[test]
+    fn thermal_conserves_material() {
+        let erosion = ThermalErosion { iterations: 30, talus: 10.0, ..ThermalErosion::default() };
+        let mut heightmap = terrain(24);
+        let before = total(&heightmap);
+        assert!(erosion.apply(&mut heightmap, &AtomicBool::new(false), &|_| {}));
+        assert!((total(&heightmap) - before).abs() < 1e-9);
+    }
+
+    #[test]
+    fn thermal_leaves_no_slope_above_talus() {
+        let erosion = ThermalErosion { iterations: 2000, talus: 30.0, strength: 0.5, ..ThermalErosion::default() };
+        let size = 8;
+        let mut heightmap = Heightmap { width: size, height: size, data: vec![0.0; (size * size) as usize] };
+        heightmap.data[(3 * size + 4) as usize] = 1.0;
+        erosion.apply(&mut heightmap, &AtomicBool::new(false), &|_| {});
+
+        let limit = 30f64.to_radians().tan() * 2.0 / size as f64;
+        let size = size as i64;
+        for y in 0..size {
+            for x in 0..size {
+                for &(dx, dy, distance) in &NEIGHBOURS {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if (0..size).contains(&nx) && (0..size).contains(&ny) {
+                        let drop = heightmap.data[(y * size + x) as usize] - heightmap.data[(ny * size + nx) as usize];
+                        assert!(drop <= limit * distance + 1e-6, "({}, {}) baja {}", x, y, drop);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn thermal_stops_when_cancelled() {
+        let mut heightmap = terrain(16);
+        let original = heightmap.clone();
+        assert!(!ThermalErosion::default().apply(&mut heightmap, &AtomicBool::new(true), &|_| {}));
+        assert_eq!(heightmap, original);
+    }
+}
@@ -0,0 +1,404 @@
+//! Grafo de nodos para montar la generación por piezas.
+//!
+//! Cada nodo produce un campo completo (un valor por pixel, en -1..1 más o
+//! menos) a partir de los campos de sus entradas. Los nodos de ruido usan
+//! `generate_with`, el mismo motor que la generación normal.
+
+use crate::{generate_with, Heightmap, NoiseParams};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+
+pub type NodeId = u32;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum NodeKind {
+    /// Ruido fractal; el tamaño y la zona son los de la evaluación.
+    Noise { params: NoiseParams },
+    Constant { value: f64 },
+    /// Rampa de -1 a 1 a lo ancho de la zona, girada `angle` grados.
+    Gradient { angle: f64 },
+    Add,
+    /// Mezcla `a` y `b`; con la entrada `t` conectada se usa ésta (de -1..1 a 0..1) en lugar de `factor`.
+    Mix { factor: f64 },
+    Clamp { min: f64, max: f64 },
+    /// Curva de potencia sobre el valor pasado a 0..1.
+    Curve { exponent: f64 },
+    /// Media en un cuadrado de lado `2 * radius + 1`.
+    Blur { radius: u32 },
+    /// Mínimo en un cuadrado de lado `2 * radius + 1`: estrecha las crestas.
+    Erode { radius: u32 },
+    /// Lo que se muestra en la aplicación.
+    Image,
+    /// Se guarda como PNG en `path`.
+    Export { path: String },
+}
+
+impl NodeKind {
+    /// Un nodo de cada tipo, con valores razonables, para añadir al grafo.
+    pub fn templates() -> Vec<NodeKind> {
+        use crate::NoiseKind;
+        vec![
+            NodeKind::Noise { params: NoiseParams::default() },
+            NodeKind::Noise { params: NoiseParams { noise: NoiseKind::Worley, ..NoiseParams::default() } },
+            NodeKind::Constant { value: 0.0 },
+            NodeKind::Gradient { angle: 90.0 },
+            NodeKind::Add,
+            NodeKind::Mix { factor: 0.5 },
+            NodeKind::Clamp { min: -0.5, max: 0.5 },
+            NodeKind::Curve { exponent: 2.0 },
+            NodeKind::Blur { radius: 2 },
+            NodeKind::Erode { radius: 1 },
+            NodeKind::Image,
+            NodeKind::Export { path: String::from("grafo.png") },
+        ]
+    }
+
+    /// Nombres de las entradas, en orden.
+    pub fn inputs(&self) -> &'static [&'static str] {
+        match self {
+            NodeKind::Noise { .. } | NodeKind::Constant { .. } | NodeKind::Gradient { .. } => &[],
+            NodeKind::Add => &["a", "b"],
+            NodeKind::Mix { .. } => &["a", "b", "t"],
+            NodeKind::Clamp { .. } | NodeKind::Curve { .. } | NodeKind::Blur { .. } | NodeKind::Erode { .. }
+            | NodeKind::Image | NodeKind::Export { .. } => &["entrada"],
+        }
+    }
+
+    /// Las salidas (imagen y exportar) no se pueden conectar a nada.
+    pub fn has_output(&self) -> bool {
+        !self.is_sink()
+    }
+
+    pub fn is_sink(&self) -> bool {
+        matches!(self, NodeKind::Image | NodeKind::Export { .. })
+    }
+}
+
+impl fmt::Display for NodeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeKind::Noise { params } => write!(f, "Ruido {}", params.noise),
+            NodeKind::Constant { .. } => f.write_str("Constante"),
+            NodeKind::Gradient { .. } => f.write_str("Degradado"),
+            NodeKind::Add => f.write_str("Sumar"),
+            NodeKind::Mix { .. } => f.write_str("Mezclar"),
+            NodeKind::Clamp { .. } => f.write_str("Recortar"),
+            NodeKind::Curve { .. } => f.write_str("Curva"),
+            NodeKind::Blur { .. } => f.write_str("Desenfocar"),
+            NodeKind::Erode { .. } => f.write_str("Erosionar"),
+            NodeKind::Image => f.write_str("Imagen"),
+            NodeKind::Export { .. } => f.write_str("Exportar"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub id: NodeId,
+    pub kind: NodeKind,
+    /// Esquina superior izquierda en el editor.
+    pub position: [f32; 2],
+}
+
+/// Conexión de la salida de `from` a la entrada `input` de `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Wire {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub input: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub wires: Vec<Wire>,
+}
+
+/// Resultado de evaluar las salidas del grafo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub image: Option<Heightmap>,
+    /// Ruta y campo de cada nodo de exportar.
+    pub exports: Vec<(String, Heightmap)>,
+}
+
+impl Graph {
+    /// Ruido conectado a la imagen: el mismo resultado que sin grafo.
+    pub fn starter(params: &NoiseParams) -> Graph {
+        let mut graph = Graph::default();
+        let noise = graph.add(NodeKind::Noise { params: NoiseParams { layers: Vec::new(), ..params.clone() } }, [40.0, 60.0]);
+        let image = graph.add(NodeKind::Image, [300.0, 60.0]);
+        graph.wires.push(Wire { from: noise, to: image, input: 0 });
+        graph
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes.iter_mut().find(|node| node.id == id)
+    }
+
+    pub fn add(&mut self, kind: NodeKind, position: [f32; 2]) -> NodeId {
+        let id = self.nodes.iter().map(|node| node.id + 1).max().unwrap_or(0);
+        self.nodes.push(Node { id, kind, position });
+        id
+    }
+
+    /// Quita el nodo y todos sus cables.
+    pub fn remove(&mut self, id: NodeId) {
+        self.nodes.retain(|node| node.id != id);
+        self.wires.retain(|wire| wire.from != id && wire.to != id);
+    }
+
+    /// Conecta sustituyendo lo que hubiera en esa entrada. Rechaza puertos
+    /// que no existen y cables que cerrarían un ciclo.
+    pub fn connect(&mut self, wire: Wire) -> Result<(), String> {
+        let (Some(from), Some(to)) = (self.node(wire.from), self.node(wire.to)) else {
+            return Err(String::from("el cable une nodos que no existen"));
+        };
+        if !from.kind.has_output() {
+            return Err(format!("{} no tiene salida", from.kind));
+        }
+        if wire.input >= to.kind.inputs().len() {
+            return Err(format!("{} no tiene entrada {}", to.kind, wire.input));
+        }
+        if wire.from == wire.to || self.depends_on(wire.from, wire.to) {
+            return Err(String::from("ese cable cerraría un ciclo"));
+        }
+
+        self.disconnect(wire.to, wire.input);
+        self.wires.push(wire);
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self, to: NodeId, input: usize) {
+        self.wires.retain(|wire| !(wire.to == to && wire.input == input));
+    }
+
+    pub fn input(&self, to: NodeId, input: usize) -> Option<NodeId> {
+        self.wires.iter().find(|wire| wire.to == to && wire.input == input).map(|wire| wire.from)
+    }
+
+    // Si `node` usa, directa o indirectamente, la salida de `upstream`
+    fn depends_on(&self, node: NodeId, upstream: NodeId) -> bool {
+        let mut pending = vec![node];
+        let mut seen = Vec::new();
+        while let Some(id) = pending.pop() {
+            if id == upstream {
+                return true;
+            }
+            if !seen.contains(&id) {
+                seen.push(id);
+                pending.extend(self.wires.iter().filter(|wire| wire.to == id).map(|wire| wire.from));
+            }
+        }
+        false
+    }
+
+    /// Evalúa los nodos de salida. `base` da el tamaño, la zona y `tileable`.
+    /// Las entradas sin conectar valen 0. Devuelve `Ok(None)` si se cancela.
+    pub fn evaluate(&self, base: &NoiseParams, cancel: &AtomicBool, progress: &(dyn Fn(f32) + Sync))
+        -> Result<Option<Evaluation>, String> {
+        let sinks: Vec<&Node> = self.nodes.iter().filter(|node| node.kind.is_sink()).collect();
+        if sinks.is_empty() {
+            return Err(String::from("el grafo no tiene ningún nodo de imagen ni de exportar"));
+        }
+
+        let mut evaluator = Evaluator { graph: self, base, cancel, progress, fields: HashMap::new(), visiting: Vec::new(), done: 0 };
+        let mut evaluation = Evaluation { image: None, exports: Vec::new() };
+        for sink in sinks {
+            let Some(data) = evaluator.input(sink.id, 0)? else { return Ok(None) };
+            let heightmap = Heightmap { width: base.width, height: base.height, data };
+            match &sink.kind {
+                NodeKind::Export { path } => evaluation.exports.push((path.clone(), heightmap)),
+                _ => evaluation.image = Some(heightmap),
+            }
+        }
+        Ok(Some(evaluation))
+    }
+}
+
+struct Evaluator<'a> {
+    graph: &'a Graph,
+    base: &'a NoiseParams,
+    cancel: &'a AtomicBool,
+    progress: &'a (dyn Fn(f32) + Sync),
+    fields: HashMap<NodeId, Vec<f64>>, // ya calculados, para no repetir ramas compartidas
+    visiting: Vec<NodeId>,
+    done: usize,
+}
+
+impl Evaluator<'_> {
+    fn len(&self) -> usize {
+        self.base.width as usize * self.base.height as usize
+    }
+
+    // Campo conectado a una entrada; `None` si se ha cancelado
+    fn input(&mut self, node: NodeId, input: usize) -> Result<Option<Vec<f64>>, String> {
+        match self.graph.input(node, input) {
+            Some(from) => self.field(from),
+            None => Ok(Some(vec![0.0; self.len()])),
+        }
+    }
+
+    fn field(&mut self, id: NodeId) -> Result<Option<Vec<f64>>, String> {
+        if let Some(field) = self.fields.get(&id) {
+            return Ok(Some(field.clone()));
+        }
+        if self.visiting.contains(&id) {
+            return Err(String::from("el grafo tiene un ciclo"));
+        }
+        let node = self.graph.node(id).ok_or_else(|| format!("no existe el nodo {}", id))?;
+
+        self.visiting.push(id);
+        let field = self.compute(node)?;
+        self.visiting.pop();
+
+        let Some(field) = field else { return Ok(None) };
+        self.done += 1;
+        let total = self.graph.nodes.iter().filter(|node| !node.kind.is_sink()).count();
+        (self.progress)(self.done as f32 / total.max(1) as f32);
+        self.fields.insert(id, field.clone());
+        Ok(Some(field))
+    }
+
+    fn compute(&mut self, node: &Node) -> Result<Option<Vec<f64>>, String> {
+        let (width, height) = (self.base.width as usize, self.base.height as usize);
+        macro_rules! input {
+            ($index:expr) => {
+                match self.input(node.id, $index)? {
+                    Some(field) => field,
+                    None => return Ok(None),
+                }
+            };
+        }
+
+        let field = match &node.kind {
+            NodeKind::Noise { params } => {
+                let params = NoiseParams {
+                    width: self.base.width,
+                    height: self.base.height,
+                    region: self.base.region,
+                    tileable: self.base.tileable,
+                    ..params.clone()
+                };
+                match generate_with(&params, self.cancel, &|_| {}) {
+                    Some(heightmap) => heightmap.data,
+                    None => return Ok(None),
+                }
+            },
+            NodeKind::Constant { value } => vec![*value; self.len()],
+            NodeKind::Gradient { angle } => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                let scale = 2.0 / (cos.abs() + sin.abs()); // las esquinas de la zona llegan a ±1
+                (0..self.len())
+                    .map(|k| {
+                        let [x, y] = self.base.region.point((k % width) as f64 / width as f64,
+                                                            (k / width) as f64 / height as f64);
+                        ((x - 0.5) * cos + (y - 0.5) * sin) * scale
+                    })
+                    .collect()
+            },
+            NodeKind::Add => {
+                let (a, b) = (input!(0), input!(1));
+                a.iter().zip(&b).map(|(a, b)| a + b).collect()
+            },
+            NodeKind::Mix { factor } => {
+                let (a, b) = (input!(0), input!(1));
+                let t = match self.graph.input(node.id, 2) {
+                    Some(_) => input!(2).into_iter().map(|t| ((t + 1.0) / 2.0).clamp(0.0, 1.0)).collect(),
+                    None => vec![factor.clamp(0.0, 1.0); self.len()],
+                };
+                a.iter().zip(&b).zip(&t).map(|((a, b), t)| a + (b - a) * t).collect()
+            },
+            NodeKind::Clamp { min, max } => input!(0).into_iter().map(|v| v.clamp(*min, max.max(*min))).collect(),
+            NodeKind::Curve { exponent } => input!(0).into_iter()
+                .map(|v| ((v + 1.0) / 2.0).clamp(0.0, 1.0).powf(*exponent) * 2.0 - 1.0)
+                .collect(),
+            NodeKind::Blur { radius } => {
+                let mean = |window: &mut dyn Iterator<Item = f64>| {
+                    let (total, count) = window.fold((0.0, 0), |(total, count), v| (total + v, count + 1));
+                    total / count as f64
+                };
+                box_filter(&input!(0), width, height, *radius as usize, &mean)
+            },
+            NodeKind::Erode { radius } => {
+                let min = |window: &mut dyn Iterator<Item = f64>| window.fold(f64::INFINITY, f64::min);
+                box_filter(&input!(0), width, height, *radius as usize, &min)
+            },
+            NodeKind::Image | NodeKind::Export { .. } => input!(0),
+        };
+        Ok(Some(field))
+    }
+}
+
+/// Aplica `reduce` a la ventana de cada pixel, en dos pasadas (filas y
+/// columnas). Sirve para filtros separables como la media o el mínimo.
+fn box_filter(data: &[f64], width: usize, height: usize, radius: usize,
+              reduce: &dyn Fn(&mut dyn Iterator<Item = f64>) -> f64) -> Vec<f64> {
+    if radius == 0 || data.is_empty() {
+        return data.to_vec();
+    }
+
+    let mut rows = vec![0.0; data.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let (from, to) = (x.saturating_sub(radius), (x + radius).min(width - 1));
+            rows[y * width + x] = reduce(&mut (from..=to).map(|i| data[y * width + i]));
+        }
+    }
+
+    let mut result = vec![0.0; data.len()];
+    for y in 0..height {
+        let (from, to) = (y.saturating_sub(radius), (y + radius).min(height - 1));
+        for x in 0..width {
+            result[y * width + x] = reduce(&mut (from..=to).map(|j| rows[j * width + x]));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connect_rejects_cycles() {
+        let mut graph = Graph::default();
+        let a = graph.add(NodeKind::Curve { exponent: 2.0 }, [0.0, 0.0]);
+        let b = graph.add(NodeKind::Curve { exponent: 2.0 }, [0.0, 0.0]);
+        let c = graph.add(NodeKind::Add, [0.0, 0.0]);
+
+        assert!(graph.connect(Wire { from: a, to: b, input: 0 }).is_ok());
+        assert!(graph.connect(Wire { from: b, to: c, input: 1 }).is_ok());
+        assert!(graph.connect(Wire { from: c, to: a, input: 0 }).is_err());
+        assert!(graph.connect(Wire { from: a, to: a, input: 0 }).is_err());
+        assert_eq!(graph.wires.len(), 2);
+    }
+
+    #[test]
+    fn connect_rejects_sink_outputs_and_missing_inputs() {
+        let mut graph = Graph::default();
+        let image = graph.add(NodeKind::Image, [0.0, 0.0]);
+        let curve = graph.add(NodeKind::Curve { exponent: 2.0 }, [0.0, 0.0]);
+
+        assert!(graph.connect(Wire { from: image, to: curve, input: 0 }).is_err());
+        assert!(graph.connect(Wire { from: curve, to: image, input: 1 }).is_err());
+    }
+
+    #[test]
+    fn starter_graph_matches_generate() {
+        let params = NoiseParams { width: 32, height: 24, seed: 7, ..NoiseParams::default() };
+        let evaluation = Graph::starter(&params)
+            .evaluate(&params, &AtomicBool::new(false), &|_| {})
+            .unwrap()
+            .unwrap();
+        assert_eq!(evaluation.image.unwrap().data, crate::generate(&params).data);
+    }
+}
@@ -0,0 +1,215 @@
+//! Pila de capas de ruido que se mezclan de abajo arriba.
+//!
+//! Cada capa pasa de -1..1 a 0..1 antes de mezclarse, como una imagen en
+//! escala de grises, y el resultado vuelve a -1..1 para el resto del proceso.
+
+use crate::NoiseParams;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    Add,
+    Multiply,
+    Min,
+    Max,
+    Screen,
+    Overlay,
+    Subtract,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 7] = [
+        BlendMode::Add,
+        BlendMode::Multiply,
+        BlendMode::Min,
+        BlendMode::Max,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Subtract,
+    ];
+
+    /// Mezcla `top` sobre `base`, los dos en 0..1.
+    pub fn apply(self, base: f64, top: f64) -> f64 {
+        let value = match self {
+            BlendMode::Add => base + top,
+            BlendMode::Multiply => base * top,
+            BlendMode::Min => base.min(top),
+            BlendMode::Max => base.max(top),
+            BlendMode::Screen => 1.0 - (1.0 - base) * (1.0 - top),
+            BlendMode::Overlay => {
+                if base < 0.5 { 2.0 * base * top } else { 1.0 - 2.0 * (1.0 - base) * (1.0 - top) }
+            },
+            BlendMode::Subtract => base - top,
+        };
+        value.clamp(0.0, 1.0)
+    }
+}
+
+impl fmt::Display for BlendMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BlendMode::Add => "Sumar",
+            BlendMode::Multiply => "Multiplicar",
+            BlendMode::Min => "Mínimo",
+            BlendMode::Max => "Máximo",
+            BlendMode::Screen => "Trama",
+            BlendMode::Overlay => "Superponer",
+            BlendMode::Subtract => "Restar",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for BlendMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "add" => Ok(BlendMode::Add),
+            "multiply" => Ok(BlendMode::Multiply),
+            "min" => Ok(BlendMode::Min),
+            "max" => Ok(BlendMode::Max),
+            "screen" => Ok(BlendMode::Screen),
+            "overlay" => Ok(BlendMode::Overlay),
+            "subtract" => Ok(BlendMode::Subtract),
+            _ => Err(format!("modo de mezcla desconocido: {}", s)),
+        }
+    }
+}
+
+/// Una capa de la pila. De `params` sólo cuentan los campos del ruido: el
+/// tamaño, la zona y `tileable` son los de la imagen, y sus `layers` se ignoran.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    /// Si alguna capa está en solo, sólo se ven las que lo están.
+    pub solo: bool,
+    pub opacity: f64,
+    /// Cómo se mezcla con lo que hay debajo. La capa de más abajo se pinta
+    /// sobre negro, así que su modo no importa.
+    pub blend: BlendMode,
+    pub params: NoiseParams,
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Layer::new("Capa", NoiseParams::default())
+    }
+}
+
+impl Layer {
+    pub fn new(name: impl Into<String>, params: NoiseParams) -> Self {
+        Layer {
+            name: name.into(),
+            visible: true,
+            solo: false,
+            opacity: 1.0,
+            blend: BlendMode::Add,
+            params: NoiseParams { layers: Vec::new(), ..params },
+        }
+    }
+}
+
+/// Capas de `params` que se ven, de abajo arriba, con lo que heredan de la imagen.
+pub fn resolve(params: &NoiseParams) -> Vec<Layer> {
+    let any_solo = params.layers.iter().any(|layer| layer.solo);
+    params.layers.iter()
+        .filter(|layer| if any_solo { layer.solo } else { layer.visible })
+        .map(|layer| {
+            let mut layer = layer.clone();
+            layer.params.tileable = params.tileable;
+            layer.params.layers.clear();
+            layer
+        })
+        .collect()
+}
+
+/// Mezcla el valor de cada capa (en -1..1) y devuelve el resultado en -1..1.
+/// Sin capas visibles sale el mínimo.
+pub fn blend_stack<'a>(values: impl IntoIterator<Item = (&'a Layer, f64)>) -> f64 {
+    let mut result: Option<f64> = None;
+
+    for (layer, value) in values {
+        let top = ((value + 1.0) / 2.0).clamp(0.0, 1.0);
+        let opacity = layer.opacity.clamp(0.0, 1.0);
+        result = Some(match result {
+            None => top * opacity,
+            Some(base) => base + (layer.blend.apply(base, top) - base) * opacity,
+        });
+    }
+
+    result.unwrap_or(0.0) * 2.0 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(name: &str, visible: bool, solo: bool) -> Layer {
+        Layer { visible, solo, ..Layer::new(name, NoiseParams::default()) }
+    }
+
+    fn names(layers: &[Layer]) -> Vec<&str> {
+        layers.iter().map(|layer| layer.name.as_str()).collect()
+    }
+
+    #[test]
+    fn blend_modes() {
+        let (base, top) = (0.25, 0.5);
+        assert_eq!(BlendMode::Add.apply(base, top), 0.75);
+        assert_eq!(BlendMode::Multiply.apply(base, top), 0.125);
+        assert_eq!(BlendMode::Min.apply(base, top), 0.25);
+        assert_eq!(BlendMode::Max.apply(base, top), 0.5);
+        assert_eq!(BlendMode::Screen.apply(base, top), 0.625);
+        assert_eq!(BlendMode::Subtract.apply(base, top), 0.0);
+        // Overlay multiplica en las sombras y hace trama en las luces
+        assert_eq!(BlendMode::Overlay.apply(0.25, 0.5), 0.25);
+        assert_eq!(BlendMode::Overlay.apply(0.75, 0.5), 0.75);
+        assert_eq!(BlendMode::Overlay.apply(0.75, 1.0), 1.0);
+        // Se recorta a 0..1
+        assert_eq!(BlendMode::Add.apply(0.75, 0.5), 1.0);
+    }
+
+    #[test]
+    fn resolve_skips_hidden_layers() {
+        let params = NoiseParams {
+            tileable: true,
+            layers: vec![layer("a", true, false), layer("b", false, false), layer("c", true, false)],
+            ..NoiseParams::default()
+        };
+        let layers = resolve(&params);
+        assert_eq!(names(&layers), ["a", "c"]);
+        assert!(layers.iter().all(|layer| layer.params.tileable && layer.params.layers.is_empty()));
+    }
+
+    #[test]
+    fn solo_overrides_visibility() {
+        let params = NoiseParams {
+            layers: vec![layer("a", true, false), layer("b", false, true), layer("c", true, true)],
+            ..NoiseParams::default()
+        };
+        assert_eq!(names(&resolve(&params)), ["b", "c"]);
+    }
+
+    #[test]
+    fn blend_stack_interpolates_by_opacity() {
+        assert_eq!(blend_stack([]), -1.0);
+
+        let bottom = Layer::default();
+        assert_eq!(blend_stack([(&bottom, 0.5)]), 0.5);
+        // La de abajo se pinta sobre negro
+        let faded = Layer { opacity: 0.5, ..Layer::default() };
+        assert_eq!(blend_stack([(&faded, 1.0)]), 0.0);
+
+        // 0.5 + (0.5 + 0.5 - 0.5) * 0.5 = 0.75 en 0..1
+        let top = Layer { opacity: 0.5, blend: BlendMode::Add, ..Layer::default() };
+        assert_eq!(blend_stack([(&bottom, 0.0), (&top, 0.0)]), 0.5);
+        let hidden = Layer { opacity: 0.0, blend: BlendMode::Multiply, ..Layer::default() };
+        assert_eq!(blend_stack([(&bottom, 0.0), (&hidden, -1.0)]), 0.0);
+    }
+}
@@ -0,0 +1,591 @@
+//! Generación de ruido procedural, independiente de la interfaz.
+//!
+//! Los binarios (iced, minifb, línea de comandos) sólo construyen un
+//! `NoiseParams` y llaman a `generate`.
+
+use image::ColorType;
+use noise::{NoiseFn, OpenSimplex, Perlin, Simplex, SuperSimplex, Value, Worley};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+pub mod biome;
+pub mod erosion;
+pub mod graph;
+pub mod layers;
+pub mod mask;
+pub mod normalize;
+pub mod palette;
+pub mod postprocess;
+pub mod preset;
+pub mod rivers;
+pub mod stats;
+
+use layers::Layer;
+use normalize::Normalizer;
+use palette::Gradient;
+
+/// Fuente de ruido base sobre la que se construye el fractal.
+pub trait NoiseSource {
+    fn sample(&self, pos: [f64; 2]) -> f64;
+
+    /// Muestra que se repite cada `period` en ambos ejes.
+    ///
+    /// Por defecto mezcla las cuatro copias desplazadas un periodo, que
+    /// funciona con cualquier fuente pero apaga el contraste en el centro
+    /// de la tesela. Las fuentes con versión 4D usan un toro en su lugar.
+    fn sample_tiled(&self, pos: [f64; 2], period: [f64; 2]) -> f64 {
+        let [w, h] = period;
+        let x = pos[0].rem_euclid(w);
+        let y = pos[1].rem_euclid(h);
+        let (s, t) = (x / w, y / h);
+
+        (self.sample([x, y]) * (1.0 - s) * (1.0 - t)
+            + self.sample([x - w, y]) * s * (1.0 - t)
+            + self.sample([x, y - h]) * (1.0 - s) * t
+            + self.sample([x - w, y - h]) * s * t)
+            / (s * s + (1.0 - s) * (1.0 - s)).sqrt() // compensa parte del contraste perdido
+            / (t * t + (1.0 - t) * (1.0 - t)).sqrt()
+    }
+}
+
+/// Cada eje del plano se enrolla en una circunferencia de longitud igual al
+/// periodo, así que las distancias se conservan y el ruido no se estira.
+fn torus_point(pos: [f64; 2], period: [f64; 2]) -> [f64; 4] {
+    use std::f64::consts::TAU;
+    // Reducir antes a una vuelta hace que puntos a un periodo exacto coincidan bit a bit
+    let (a, b) = (TAU * (pos[0] / period[0]).rem_euclid(1.0), TAU * (pos[1] / period[1]).rem_euclid(1.0));
+    let (r, s) = (period[0] / TAU, period[1] / TAU);
+    [r * a.cos(), r * a.sin(), s * b.cos(), s * b.sin()]
+}
+
+macro_rules! torus_source {
+    ($($source:ty),*) => {$(
+        impl NoiseSource for $source {
+            fn sample(&self, pos: [f64; 2]) -> f64 { NoiseFn::<f64, 2>::get(self, pos) }
+
+            fn sample_tiled(&self, pos: [f64; 2], period: [f64; 2]) -> f64 {
+                NoiseFn::<f64, 4>::get(self, torus_point(pos, period))
+            }
+        }
+    )*};
+}
+
+torus_source!(Perlin, Simplex, OpenSimplex, Value, Worley);
+
+// noise 0.9 no tiene SuperSimplex en 4D: usa la mezcla por defecto
+impl NoiseSource for SuperSimplex {
+    fn sample(&self, pos: [f64; 2]) -> f64 { self.get(pos) }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoiseKind {
+    Perlin,
+    Simplex,
+    OpenSimplex,
+    Value,
+    Worley,
+    SuperSimplex,
+}
+
+impl NoiseKind {
+    pub const ALL: [NoiseKind; 6] = [
+        NoiseKind::Perlin,
+        NoiseKind::Simplex,
+        NoiseKind::OpenSimplex,
+        NoiseKind::Value,
+        NoiseKind::Worley,
+        NoiseKind::SuperSimplex,
+    ];
+
+    pub fn build(self, seed: u32) -> Box<dyn NoiseSource> {
+        match self {
+            NoiseKind::Perlin => Box::new(Perlin::new(seed)),
+            NoiseKind::Simplex => Box::new(Simplex::new(seed)),
+            NoiseKind::OpenSimplex => Box::new(OpenSimplex::new(seed)),
+            NoiseKind::Value => Box::new(Value::new(seed)),
+            NoiseKind::Worley => Box::new(Worley::new(seed)),
+            NoiseKind::SuperSimplex => Box::new(SuperSimplex::new(seed)),
+        }
+    }
+}
+
+impl fmt::Display for NoiseKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NoiseKind::Perlin => "Perlin",
+            NoiseKind::Simplex => "Simplex",
+            NoiseKind::OpenSimplex => "OpenSimplex",
+            NoiseKind::Value => "Value",
+            NoiseKind::Worley => "Worley (celular)",
+            NoiseKind::SuperSimplex => "SuperSimplex",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for NoiseKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "perlin" => Ok(NoiseKind::Perlin),
+            "simplex" => Ok(NoiseKind::Simplex),
+            "opensimplex" => Ok(NoiseKind::OpenSimplex),
+            "value" => Ok(NoiseKind::Value),
+            "worley" => Ok(NoiseKind::Worley),
+            "supersimplex" => Ok(NoiseKind::SuperSimplex),
+            _ => Err(format!("tipo de ruido desconocido: {}", s)),
+        }
+    }
+}
+
+/// Cómo se combinan las octavas del ruido base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FractalKind {
+    /// Suma de octavas (fBm).
+    Fbm,
+    /// Crestas afiladas con `1 - |n|`; cada octava pesa según la anterior (`gain`).
+    Ridged,
+    /// Octavas con `|n|` reescalado a -1..1: formas redondeadas, como nubes.
+    Billow,
+    /// Suma de `|n|` sin reescalar; el resultado queda en 0..1.
+    Turbulence,
+    /// Multifractal híbrido de Musgrave: valles suaves y cimas rugosas.
+    HybridMulti,
+    /// Terreno heterogéneo de Musgrave: la rugosidad crece con la altura.
+    HeteroTerrain,
+}
+
+impl FractalKind {
+    pub const ALL: [FractalKind; 6] = [
+        FractalKind::Fbm,
+        FractalKind::Ridged,
+        FractalKind::Billow,
+        FractalKind::Turbulence,
+        FractalKind::HybridMulti,
+        FractalKind::HeteroTerrain,
+    ];
+
+    /// Si usa `NoiseParams::offset`.
+    pub fn uses_offset(self) -> bool {
+        matches!(self, FractalKind::Ridged | FractalKind::HybridMulti | FractalKind::HeteroTerrain)
+    }
+
+    /// Si usa `NoiseParams::gain`.
+    pub fn uses_gain(self) -> bool {
+        self == FractalKind::Ridged
+    }
+}
+
+impl fmt::Display for FractalKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FractalKind::Fbm => "fBm",
+            FractalKind::Ridged => "Crestas (ridged)",
+            FractalKind::Billow => "Billow",
+            FractalKind::Turbulence => "Turbulencia",
+            FractalKind::HybridMulti => "Multifractal híbrido",
+            FractalKind::HeteroTerrain => "Terreno heterogéneo",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for FractalKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fbm" => Ok(FractalKind::Fbm),
+            "ridged" => Ok(FractalKind::Ridged),
+            "billow" => Ok(FractalKind::Billow),
+            "turbulence" => Ok(FractalKind::Turbulence),
+            "hybridmulti" => Ok(FractalKind::HybridMulti),
+            "heteroterrain" => Ok(FractalKind::HeteroTerrain),
+            _ => Err(format!("tipo de fractal desconocido: {}", s)),
+        }
+    }
+}
+
+/// Zona del plano de ruido que cubre la imagen. La imagen completa por
+/// defecto es el cuadrado unidad; al hacer zoom se muestrea una zona menor.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Default for Region {
+    fn default() -> Self {
+        Region { x: 0.0, y: 0.0, width: 1.0, height: 1.0 }
+    }
+}
+
+impl Region {
+    /// Coordenada de muestreo para la posición `(u, v)` en 0..1 dentro de la imagen.
+    pub fn point(&self, u: f64, v: f64) -> [f64; 2] {
+        [self.x + u * self.width, self.y + v * self.height]
+    }
+
+    /// Inversa de `point`; queda fuera de 0..1 si `pos` cae fuera de la zona.
+    pub fn uv(&self, pos: [f64; 2]) -> [f64; 2] {
+        [(pos[0] - self.x) / self.width, (pos[1] - self.y) / self.height]
+    }
+}
+
+/// Campo de ruido que desplaza las coordenadas antes de muestrear.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WarpField {
+    pub octaves: u32,
+    pub frequency: f64,
+    /// Desplazamiento máximo, en unidades del plano de ruido.
+    pub strength: f64,
+}
+
+impl Default for WarpField {
+    fn default() -> Self {
+        WarpField { octaves: 4, frequency: 1.0, strength: 0.5 }
+    }
+}
+
+/// Parámetros de generación, ya escalados a sus valores reales.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseParams {
+    pub noise: NoiseKind,
+    pub fractal: FractalKind,
+    pub octaves: u32,
+    pub lacunarity: f64,
+    pub persistence: f64,
+    pub frequency: f64,
+    pub amplitude: f64,
+    /// Desplazamiento de la señal en los modos multifractales.
+    pub offset: f64,
+    /// Cuánto pesa cada octava en la siguiente en el modo de crestas.
+    pub gain: f64,
+    pub seed: u32,
+    /// Hace que la imagen se repita sin costuras, con el cuadrado unidad del
+    /// plano como tesela.
+    pub tileable: bool,
+    /// Deformación del dominio; cada campo se muestrea sobre las coordenadas
+    /// ya deformadas por el anterior. Vacío para no deformar.
+    pub warp: Vec<WarpField>,
+    /// Capas que se mezclan para formar la imagen, de abajo arriba. Si hay
+    /// alguna, los campos de ruido de aquí no se usan: sólo el tamaño, la
+    /// zona y `tileable`, que son comunes a todas.
+    pub layers: Vec<Layer>,
+    pub width: u32,
+    pub height: u32,
+    pub region: Region,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        NoiseParams {
+            noise: NoiseKind::Perlin,
+            fractal: FractalKind::Fbm,
+            octaves: 8,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            frequency: 0.5,
+            amplitude: 0.5,
+            offset: 1.0,
+            gain: 2.0,
+            seed: 0,
+            tileable: false,
+            warp: Vec::new(),
+            layers: Vec::new(),
+            width: 1000,
+            height: 600,
+            region: Region::default(),
+        }
+    }
+}
+
+/// Campo de valores de ruido, fila a fila.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heightmap {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<f64>,
+}
+
+impl Heightmap {
+    pub fn get(&self, x: u32, y: u32) -> f64 {
+        self.data[y as usize * self.width as usize + x as usize]
+    }
+
+    /// Pixels RGBA coloreados con el degradado, tras pasar cada valor a 0..1
+    /// con `normalizer` (normalmente ajustado a este mismo heightmap).
+    pub fn to_rgba(&self, gradient: &Gradient, normalizer: &Normalizer) -> Vec<u8> {
+        const LUT_SIZE: usize = 1024;
+        let lut = gradient.lut(LUT_SIZE);
+        let mut pixels = Vec::with_capacity(self.data.len() * 4);
+
+        for &value in &self.data {
+            let t = normalizer.apply(value);
+            let [r, g, b] = lut[(t * (LUT_SIZE - 1) as f64).round() as usize];
+            pixels.extend_from_slice(&[r, g, b, 255]); // RGBA
+        }
+        pixels
+    }
+}
+
+/// Guarda un buffer RGBA como PNG.
+pub fn save_png(path: impl AsRef<Path>, width: u32, height: u32, pixels: &[u8]) -> Result<(), image::ImageError> {
+    image::save_buffer(path, pixels, width, height, ColorType::Rgba8)
+}
+
+pub fn fractal_noise(source: &dyn NoiseSource, pos: [f64; 2], octaves: u32, lacunarity: f64, persistence: f64,
+                    frequency: f64, amplitude: f64) -> f64 {
+    fbm(&|f| sample_octave(source, pos, f, false), octaves, lacunarity, persistence, frequency, amplitude)
+}
+
+/// Suma de octavas; `octave(f)` devuelve el ruido base a la frecuencia `f`.
+pub(crate) fn fbm(octave: &dyn Fn(f64) -> f64, octaves: u32, lacunarity: f64, persistence: f64,
+       mut frequency: f64, mut amplitude: f64) -> f64 {
+    let mut total = 0.0;
+    let mut maxvalue = 0.0;
+
+    for _ in 0..octaves {
+        total += octave(frequency) * amplitude;
+
+        maxvalue += amplitude;
+        amplitude *= persistence;
+        frequency *= lacunarity;
+    }
+
+    total / maxvalue // Normalizamos a -1.0..1.0 (más o menos)
+}
+
+/// Ruido base en `pos` a la frecuencia dada. Las texturas repetibles tienen
+/// como tesela el cuadrado unidad del plano, que a esa frecuencia mide `frequency`.
+pub(crate) fn sample_octave(source: &dyn NoiseSource, pos: [f64; 2], frequency: f64, tileable: bool) -> f64 {
+    let pos = [pos[0] * frequency, pos[1] * frequency];
+    if tileable {
+        source.sample_tiled(pos, [frequency, frequency])
+    } else {
+        source.sample(pos)
+    }
+}
+
+/// Valor del ruido en un punto del plano, el mismo que tendría un pixel de
+/// `generate` muestreado justo ahí.
+pub fn sample_at(params: &NoiseParams, pos: [f64; 2]) -> f64 {
+    let layers = layers::resolve(params);
+    sample_image(&build_sources(params, &layers), params, &layers, pos)
+}
+
+/// Una fuente por capa visible, o una sola si no hay capas.
+fn build_sources(params: &NoiseParams, layers: &[Layer]) -> Vec<Box<dyn NoiseSource>> {
+    if params.layers.is_empty() {
+        return vec![params.noise.build(params.seed)];
+    }
+    layers.iter().map(|layer| layer.params.noise.build(layer.params.seed)).collect()
+}
+
+// `layers` son las de `layers::resolve(params)`, con `sources` en el mismo orden
+fn sample_image(sources: &[Box<dyn NoiseSource>], params: &NoiseParams, layers: &[Layer], pos: [f64; 2]) -> f64 {
+    if params.layers.is_empty() {
+        return sample_with(sources[0].as_ref(), params, pos);
+    }
+    layers::blend_stack(layers.iter().zip(sources).map(|(layer, source)| {
+        (layer, sample_with(source.as_ref(), &layer.params, pos))
+    }))
+}
+
+fn sample_with(source: &dyn NoiseSource, params: &NoiseParams, pos: [f64; 2]) -> f64 {
+    let pos = warp(source, params, pos);
+    let octave = |f| sample_octave(source, pos, f, params.tileable);
+    if params.fractal == FractalKind::Fbm {
+        return fbm(&octave, params.octaves, params.lacunarity, params.persistence,
+                   params.frequency, params.amplitude);
+    }
+    multifractal(&octave, params)
+}
+
+/// Deformación iterada al estilo de Quilez: `p + s₂·q₂(p + s₁·q₁(p))`.
+/// Cada componente del desplazamiento sale de la misma fuente, muestreada
+/// lejos para que no se parezcan entre sí.
+fn warp(source: &dyn NoiseSource, params: &NoiseParams, pos: [f64; 2]) -> [f64; 2] {
+    const SHIFTS: [[f64; 2]; 2] = [[0.0, 0.0], [5.2, 1.3]];
+    let mut displacement = [0.0, 0.0];
+
+    for (k, field) in params.warp.iter().enumerate() {
+        let base = [pos[0] + displacement[0] + 17.8 * k as f64, pos[1] + displacement[1] + 9.2 * k as f64];
+        displacement = SHIFTS.map(|shift| {
+            let p = [base[0] + shift[0], base[1] + shift[1]];
+            let octave = |f| sample_octave(source, p, f, params.tileable);
+            fbm(&octave, field.octaves, 2.0, 0.5, field.frequency, 1.0) * field.strength
+        });
+    }
+
+    [pos[0] + displacement[0], pos[1] + displacement[1]]
+}
+
+/// Todos los modos de `FractalKind`; `sample_with` usa `fbm` directamente
+/// para el fBm, pero aquí da lo mismo. La octava `i` pesa `persistence^i` y
+/// el resultado se normaliza a -1..1 (más o menos) dividiendo por el máximo
+/// teórico; la amplitud inicial no cambia nada tras normalizar.
+fn multifractal(octave: &dyn Fn(f64) -> f64, params: &NoiseParams) -> f64 {
+    let offset = params.offset;
+    let mut frequency = params.frequency;
+    let mut amplitude = 1.0;
+    let mut total = 0.0;
+    let mut maxvalue = 0.0;
+    let mut weight = 1.0;
+
+    for i in 0..params.octaves {
+        let n = octave(frequency);
+
+        match params.fractal {
+            FractalKind::Fbm => {
+                total += n * amplitude;
+                maxvalue += amplitude;
+            },
+            FractalKind::Ridged => {
+                let signal = (offset - n.abs()).powi(2) * weight;
+                weight = (signal * params.gain).clamp(0.0, 1.0);
+                total += signal * amplitude;
+                maxvalue += offset * offset * amplitude;
+            },
+            FractalKind::Billow => {
+                total += (2.0 * n.abs() - 1.0) * amplitude;
+                maxvalue += amplitude;
+            },
+            FractalKind::Turbulence => {
+                total += n.abs() * amplitude;
+                maxvalue += amplitude;
+            },
+            FractalKind::HybridMulti => {
+                let signal = (n + offset) * amplitude;
+                total += if i == 0 { signal } else { weight.min(1.0) * signal };
+                weight = if i == 0 { signal } else { weight.min(1.0) * signal };
+                maxvalue += (1.0 + offset) * amplitude;
+            },
+            FractalKind::HeteroTerrain => {
+                // Cada octava se escala por la altura acumulada hasta ahora
+                total = if i == 0 { n + offset } else { total + (n + offset) * amplitude * total };
+                maxvalue = if i == 0 { 1.0 + offset } else { maxvalue + (1.0 + offset) * amplitude };
+            },
+        }
+
+        amplitude *= params.persistence;
+        frequency *= params.lacunarity;
+    }
+
+    if maxvalue == 0.0 {
+        return 0.0;
+    }
+    match params.fractal {
+        FractalKind::Fbm | FractalKind::Billow | FractalKind::Turbulence => total / maxvalue,
+        _ => total / maxvalue * 2.0 - 1.0, // de 0..1 a -1..1
+    }
+}
+
+/// Muestrea el ruido fractal sobre `params.region` con la resolución pedida.
+pub fn generate(params: &NoiseParams) -> Heightmap {
+    generate_with(params, &AtomicBool::new(false), &|_| {})
+        .expect("una generación sin cancelar siempre termina")
+}
+
+/// Referencia en un solo hilo, recorriendo las filas en orden: `generate`
+/// tiene que dar exactamente lo mismo.
+pub fn generate_serial(params: &NoiseParams) -> Heightmap {
+    let layers = layers::resolve(params);
+    let sources = build_sources(params, &layers);
+    let mut data = Vec::with_capacity(params.width as usize * params.height as usize);
+    for j in 0..params.height {
+        let v = j as f64 / params.height as f64;
+        for i in 0..params.width {
+            let u = i as f64 / params.width as f64;
+            data.push(sample_image(&sources, params, &layers, params.region.point(u, v)));
+        }
+    }
+    Heightmap { width: params.width, height: params.height, data }
+}
+
+/// Igual que `generate`, pero llama a `progress` con el número de filas ya
+/// terminadas y abandona en cuanto se activa `cancel`, devolviendo `None`.
+///
+/// Las filas se reparten entre todos los núcleos; cada pixel sólo depende de
+/// su posición, así que el resultado es idéntico al de recorrerlas en orden.
+pub fn generate_with(params: &NoiseParams, cancel: &AtomicBool, progress: &(dyn Fn(u32) + Sync)) -> Option<Heightmap> {
+    let mut data = vec![0.0; params.width as usize * params.height as usize];
+    let rows_done = AtomicU32::new(0);
+    let layers = layers::resolve(params);
+
+    if !data.is_empty() {
+        data.par_chunks_mut(params.width as usize)
+            .enumerate()
+            .try_for_each_init(
+                // Algunas fuentes (Worley) no son Sync: cada hilo construye las suyas
+                || build_sources(params, &layers),
+                |sources, (j, row)| {
+                    if cancel.load(Ordering::Relaxed) {
+                        return None;
+                    }
+                    let v = j as f64 / params.height as f64;
+                    for (i, value) in row.iter_mut().enumerate() {
+                        let u = i as f64 / params.width as f64;
+                        *value = sample_image(sources, params, &layers, params.region.point(u, v));
+                    }
+                    progress(rows_done.fetch_add(1, Ordering::Relaxed) + 1);
+                    Some(())
+                },
+            )?;
+    }
+
+    Some(Heightmap { width: params.width, height: params.height, data })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(heightmap: &Heightmap) -> Vec<u64> {
+        heightmap.data.iter().map(|v| v.to_bits()).collect()
+    }
+
+    #[test]
+    fn multifractal_handles_fbm() {
+        let source = Perlin::new(3);
+        for params in [NoiseParams::default(), NoiseParams { amplitude: 0.3, octaves: 5, ..NoiseParams::default() }] {
+            for pos in [[0.1, 0.2], [0.7, 0.4], [2.5, -1.3]] {
+                let octave = |f| sample_octave(&source, pos, f, false);
+                let expected = fbm(&octave, params.octaves, params.lacunarity, params.persistence,
+                                   params.frequency, params.amplitude);
+                assert!((multifractal(&octave, &params) - expected).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_matches_serial_for_any_pool_size() {
+        let params = NoiseParams {
+            noise: NoiseKind::Worley,
+            fractal: FractalKind::Ridged,
+            seed: 1234,
+            warp: vec![WarpField { octaves: 3, frequency: 1.5, strength: 0.4 }],
+            width: 67,
+            height: 41,
+            ..NoiseParams::default()
+        };
+        let serial = bits(&generate_serial(&params));
+
+        for threads in [1, 2, 7] {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let parallel = pool.install(|| generate(&params));
+            assert_eq!(bits(&parallel), serial, "{} hilos", threads);
+        }
+    }
+}
@@ -1,169 +1,2308 @@
 use iced::{
     Theme,
+    keyboard,
     mouse,
     widget::{
         button,
-        canvas::{Canvas, Event, Frame, Geometry, Program, Image as CanvasImage},
-        row, column, slider, container, text, rule,
+        canvas::{self, Canvas, Event, Frame, Geometry, Path, Program, Stroke, Image as CanvasImage},
+        row, column, Column, Row, slider, container, text, rule, pick_list, text_input, checkbox, scrollable,
+        progress_bar,
     },
-    widget::image::Handle, widget::scrollable::Scrollbar, widget::scrollable::Direction,
-    Element, Length, Rectangle,
+    widget::image::{FilterMethod, Handle},
+    widget::Action,
+    Color, Element, Length, Point, Rectangle, Size, Subscription, Task, Vector,
 };
 
-use noise::{NoiseFn, Perlin};
+use iced::futures::channel::mpsc;
+use iced::futures::{SinkExt, Stream, StreamExt};
 use rand::Rng;
+use ruprogen::biome::{BiomeMap, BiomeSettings, MoistureField};
+use ruprogen::erosion::{HydraulicErosion, ThermalErosion};
+use ruprogen::graph::{Evaluation, Graph, Node, NodeId, NodeKind, Wire};
+use ruprogen::layers::{BlendMode, Layer};
+use ruprogen::mask::{MaskMode, MaskShape};
+use ruprogen::normalize::{Normalization, NormalizeMode, Normalizer};
+use ruprogen::palette::{self, Gradient, GradientMode, GradientPreset};
+use ruprogen::postprocess::PostProcess;
+use ruprogen::preset::{self, Preset};
+use ruprogen::rivers::{RiverNetwork, RiverSettings};
+use ruprogen::stats::FieldStats;
+use ruprogen::{generate_with, sample_at, save_png, FractalKind, Heightmap, NoiseKind, NoiseParams, Region, WarpField};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::PathBuf;
+use std::collections::VecDeque;
+use std::mem::Discriminant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-fn fractal_noise(perlin: &Perlin, pos: [f64; 2], octaves: u32, lacunarity: f64, persistence: f64,
-                    mut frequency: f64, mut amplitude: f64) -> f64 {
-    let mut total = 0.0;
-    // let mut frequency = 1.0;
-    // let mut amplitude = 1.0;
-    let mut maxvalue = 0.0;
+#[derive(Debug, Clone)]
+enum JobEvent {
+    Progress(f32),
+    Finished(Generated),
+    Evaluated(Evaluation),
+    Failed(String),
+}
+
+// Resultado de una generación normal
+#[derive(Debug, Clone)]
+struct Generated {
+    heightmap: Heightmap,
+    before: Option<Heightmap>, // el campo antes del postproceso, si lo hay
+    moisture: Option<Heightmap>, // la humedad, si hay biomas
+    rivers: Option<RiverNetwork>,
+}
+
+// Genera en el pool de tokio; el stream termina sin `Finished` si se cancela
+fn apply_perlin(params: NoiseParams, post: PostProcess, moisture: Option<MoistureField>, rivers: Option<RiverSettings>,
+                cancel: Arc<AtomicBool>) -> impl Stream<Item = JobEvent> {
+    iced::stream::channel(16, move |mut output: mpsc::Sender<JobEvent>| async move {
+        let (sender, mut progress) = mpsc::unbounded();
+
+        let worker = tokio::task::spawn_blocking(move || {
+            let rows = params.height;
+            // Con erosión, el muestreo es la primera mitad de la barra; la
+            // máscara es instantánea y no cuenta
+            let erodes = post.hydraulic.enabled || post.thermal.enabled;
+            let share = if erodes { 0.5 } else { 1.0 };
+            let Some(mut heightmap) = generate_with(&params, &cancel, &|done| {
+                // Sólo avisamos cuando cambia el porcentaje
+                if done * 100 / rows != (done - 1) * 100 / rows {
+                    let _ = sender.unbounded_send(done as f32 / rows as f32 * share);
+                }
+            }) else {
+                return Ok(None);
+            };
+            // Los cauces también cambian el heightmap
+            let carve = rivers.is_some_and(|rivers| rivers.carve);
+            let before = (post.is_active() || carve).then(|| heightmap.clone());
+            if post.is_active() && !post.apply(&mut heightmap, params.seed, &cancel, &|done| {
+                let _ = sender.unbounded_send(0.5 + done * 0.5);
+            })? {
+                return Ok(None);
+            }
+            // Los ríos no miran `cancel`; al menos no se empiezan ni se entregan si ya sobran
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            let rivers = rivers.map(|rivers| rivers.apply(&mut heightmap));
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            let moisture = match moisture {
+                Some(field) => match field.generate(&params, &cancel) {
+                    Some(moisture) => Some(moisture),
+                    None => return Ok(None),
+                },
+                None => None,
+            };
+            Ok(Some(Generated { heightmap, before, moisture, rivers }))
+        });
+
+        while let Some(done) = progress.next().await {
+            let _ = output.send(JobEvent::Progress(done)).await;
+        }
+        match worker.await.expect("Blocking task to finish") {
+            Ok(Some(generated)) => { let _ = output.send(JobEvent::Finished(generated)).await; },
+            Ok(None) => {},
+            Err(error) => { let _ = output.send(JobEvent::Failed(error)).await; },
+        }
+    })
+}
+
+// Evalúa el grafo igual que `apply_perlin` genera una imagen
+fn apply_graph(graph: Graph, params: NoiseParams, cancel: Arc<AtomicBool>) -> impl Stream<Item = JobEvent> {
+    iced::stream::channel(16, move |mut output: mpsc::Sender<JobEvent>| async move {
+        let (sender, mut progress) = mpsc::unbounded();
+
+        let worker = tokio::task::spawn_blocking(move || {
+            graph.evaluate(&params, &cancel, &|done| {
+                let _ = sender.unbounded_send(done);
+            })
+        });
+
+        while let Some(done) = progress.next().await {
+            let _ = output.send(JobEvent::Progress(done)).await;
+        }
+        match worker.await.expect("Blocking task to finish") {
+            Ok(Some(evaluation)) => { let _ = output.send(JobEvent::Evaluated(evaluation)).await; },
+            Ok(None) => {},
+            Err(error) => { let _ = output.send(JobEvent::Failed(error)).await; },
+        }
+    })
+}
+
+// Generación en curso
+struct Job {
+    id: u64,
+    params: NoiseParams, // a resolución completa, aunque sea una vista previa
+    post: PostProcess, // también a resolución completa
+    moisture: Option<MoistureField>, // si hay biomas
+    rivers: Option<RiverSettings>, // si hay ríos
+    graph: Option<Graph>, // el que se evalúa, si no es una generación normal
+    preset: Preset, // estado completo al lanzarla, para la miniatura
+    preview: bool,
+    cancel: Arc<AtomicBool>,
+    progress: f32,
+}
+
+// La vista previa se calcula a 1/PREVIEW_DIVISOR de la resolución en cada eje
+const PREVIEW_DIVISOR: u32 = 4;
+const HISTOGRAM_BINS: usize = 128;
+const DEBOUNCE: Duration = Duration::from_millis(400);
+// Pasos de deshacer que se guardan, y miniaturas de imágenes anteriores
+const HISTORY_LIMIT: usize = 100;
+const THUMBNAIL_LIMIT: usize = 12;
+const THUMBNAIL_SIZE: u32 = 96;
+// Mensajes iguales seguidos en este margen (arrastrar un slider) son un solo paso
+const COALESCE: Duration = Duration::from_millis(600);
+
+fn main() -> iced::Result {
+    // iced::run("Canvas con imagen", PaintApp::update, PaintApp::view)
+    iced::application(PaintApp::new, PaintApp::update, PaintApp::view)
+        .subscription(PaintApp::subscription)
+        .theme(Theme::CatppuccinMocha)
+        .title("Generador de Ruido")
+        .run()
+}
+
+trait Scallable {
+    fn scale(&self) -> f64;
+}
+
+#[derive(Clone)]
+struct BoundedParam {
+    val: u32,
+    min: u32,
+    max: u32,
+    step: u32,
+}
+
+#[derive(Clone)]
+struct ScaledBoundedParam {
+    val: u32,
+    min: u32,
+    max: u32,
+    step: u32,
+    scale: f64,
+}
+
+impl Scallable for ScaledBoundedParam {
+    fn scale(&self) -> f64 { self.val as f64 / self.scale }
+}
+
+impl BoundedParam {
+    fn set(&mut self, val: u32) {
+        self.val = val.clamp(self.min, self.max);
+    }
+}
+
+impl ScaledBoundedParam {
+    // Valor real más cercano que admite el slider
+    fn set_scaled(&mut self, value: f64) {
+        self.val = ((value * self.scale).round().max(0.0) as u32).clamp(self.min, self.max);
+    }
+}
+
+// Controles de uno de los campos de deformación del dominio
+#[derive(Clone)]
+struct WarpControls {
+    octaves: BoundedParam,
+    frequency: ScaledBoundedParam,
+    strength: ScaledBoundedParam,
+}
+
+impl Default for WarpControls {
+    fn default() -> Self {
+        WarpControls {
+            octaves: BoundedParam { val: 4, min: 1, max: 10, step: 1 },
+            frequency: ScaledBoundedParam { val: 100, min: 1, max: 2000, step: 10, scale: 100.0 },
+            strength: ScaledBoundedParam { val: 50, min: 0, max: 400, step: 5, scale: 100.0 },
+        }
+    }
+}
+
+impl WarpControls {
+    fn field(&self) -> WarpField {
+        WarpField {
+            octaves: self.octaves.val,
+            frequency: self.frequency.scale(),
+            strength: self.strength.scale(),
+        }
+    }
+}
+
+// Resultado de una generación, con los pixels a su resolución real
+#[derive(Clone)]
+struct GeneratedImage {
+    width: u32,
+    height: u32,
+    display_width: u32, // tamaño al que se pinta; mayor que el real en las vistas previas
+    display_height: u32,
+    preset: Preset, // con el que se lanzó; sus parámetros van a resolución completa
+    heightmap: Heightmap,
+    normalizer: Normalizer, // ajustado a este heightmap
+    stats: FieldStats,
+    histogram: Vec<u32>,
+    pixels: Vec<u8>, // RGBA
+    handle: Handle,
+    from_graph: bool, // sin fórmula que muestrear: las sondas leen el pixel
+    preview: bool, // a 1/PREVIEW_DIVISOR de la resolución; no se exporta
+    before: Option<(Heightmap, Handle)>, // el campo sin postprocesar, coloreado igual
+    moisture: Option<Heightmap>,
+    biome_map: Option<BiomeMap>, // si hay humedad y los biomas están activos
+    rivers: Option<RiverNetwork>,
+}
+
+impl GeneratedImage {
+    fn new(heightmap: Heightmap, preset: Preset, gradient: &Gradient, normalization: &Normalization) -> Self {
+        let normalizer = normalization.fit(&heightmap.data);
+        let pixels = heightmap.to_rgba(gradient, &normalizer);
+        let stats = FieldStats::new(&heightmap.data);
+        GeneratedImage {
+            histogram: stats.histogram(HISTOGRAM_BINS),
+            stats,
+            width: heightmap.width,
+            height: heightmap.height,
+            display_width: preset.params.width,
+            display_height: preset.params.height,
+            preset,
+            handle: Handle::from_rgba(heightmap.width, heightmap.height, pixels.clone()),
+            heightmap,
+            normalizer,
+            pixels,
+            from_graph: false,
+            preview: false,
+            before: None,
+            moisture: None,
+            biome_map: None,
+            rivers: None,
+        }
+    }
+
+    // Guarda el campo sin postprocesar para compararlo con el resultado
+    fn with_before(mut self, before: Heightmap, gradient: &Gradient) -> Self {
+        let pixels = before.to_rgba(gradient, &self.normalizer);
+        self.before = Some((before, Handle::from_rgba(self.width, self.height, pixels)));
+        self
+    }
+
+    // Guarda la humedad y pinta los biomas en lugar de la paleta
+    fn with_moisture(mut self, moisture: Heightmap, gradient: &Gradient, biomes: &BiomeSettings) -> Self {
+        self.moisture = Some(moisture);
+        self.recolor(gradient, biomes);
+        self
+    }
+
+    // Con una tabla de biomas no válida se pinta con la paleta; el editor muestra el error
+    fn recolor(&mut self, gradient: &Gradient, biomes: &BiomeSettings) {
+        self.biome_map = self.moisture.as_ref()
+            .filter(|_| biomes.enabled)
+            .and_then(|moisture| biomes.classify(&self.heightmap, &self.normalizer, moisture).ok());
+        self.pixels = match &self.biome_map {
+            Some(map) => map.to_rgba(biomes),
+            None => self.heightmap.to_rgba(gradient, &self.normalizer),
+        };
+        self.handle = Handle::from_rgba(self.width, self.height, self.pixels.clone());
+        if let Some((before, handle)) = &mut self.before {
+            *handle = Handle::from_rgba(self.width, self.height, before.to_rgba(gradient, &self.normalizer));
+        }
+    }
+
+    fn renormalize(&mut self, gradient: &Gradient, normalization: &Normalization, biomes: &BiomeSettings) {
+        self.normalizer = normalization.fit(&self.heightmap.data);
+        self.recolor(gradient, biomes);
+    }
+
+    // Pixel del heightmap bajo un punto en coordenadas de pantalla de la imagen
+    fn pixel_at(&self, point: Point) -> Option<(u32, u32)> {
+        let u = point.x / self.display_width as f32;
+        let v = point.y / self.display_height as f32;
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+        Some(((u * self.width as f32) as u32, (v * self.height as f32) as u32))
+    }
+
+    fn sample_point(&self, point: Point) -> [f64; 2] {
+        self.preset.params.region.point(
+            point.x as f64 / self.display_width as f64,
+            point.y as f64 / self.display_height as f64,
+        )
+    }
+
+    // Valor exacto en un punto del plano, o el del pixel más cercano si viene
+    // del grafo o se ha postprocesado
+    fn value_at(&self, pos: [f64; 2]) -> f64 {
+        if !self.from_graph && self.before.is_none() {
+            return sample_at(&self.preset.params, pos);
+        }
+        let [u, v] = self.preset.params.region.uv(pos);
+        let x = ((u * self.width as f64) as u32).min(self.width - 1);
+        let y = ((v * self.height as f64) as u32).min(self.height - 1);
+        self.heightmap.get(x, y)
+    }
+
+    fn display_point(&self, pos: [f64; 2]) -> Point {
+        let [u, v] = self.preset.params.region.uv(pos);
+        Point::new((u * self.display_width as f64) as f32, (v * self.display_height as f64) as f32)
+    }
+}
+
+// Cómo se coloca la imagen en el lienzo: pantalla = offset + pixel * scale
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ViewTransform {
+    scale: f32,
+    offset: Vector,
+}
+
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 64.0;
+
+impl ViewTransform {
+    // Imagen completa y centrada en el lienzo
+    fn fit(canvas: Size, width: u32, height: u32) -> Self {
+        let scale = (canvas.width / width as f32).min(canvas.height / height as f32);
+        ViewTransform {
+            scale,
+            offset: Vector::new(
+                (canvas.width - width as f32 * scale) / 2.0,
+                (canvas.height - height as f32 * scale) / 2.0,
+            ),
+        }
+    }
+
+    fn to_image(self, point: Point) -> Point {
+        Point::new((point.x - self.offset.x) / self.scale, (point.y - self.offset.y) / self.scale)
+    }
+
+    // Zoom que deja fijo el punto de la imagen bajo el cursor
+    fn zoom_at(self, cursor: Point, factor: f32) -> Self {
+        let scale = (self.scale * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let k = scale / self.scale;
+        ViewTransform {
+            scale,
+            offset: Vector::new(cursor.x - (cursor.x - self.offset.x) * k, cursor.y - (cursor.y - self.offset.y) * k),
+        }
+    }
+}
+
+#[derive(Default)]
+struct CanvasState {
+    drag: Option<Point>, // última posición del cursor mientras se arrastra
+}
+
+// Lo que hay bajo el cursor, en coordenadas de pantalla de la imagen
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Hover {
+    point: Point,
+}
+
+#[derive(Clone, Debug)]
+struct PngError(String);
+
+async fn save_to_png(path: String, width: u32, height: u32, pixels: Vec<u8>) -> Result<String, PngError> {
+    tokio::task::spawn_blocking(move || {
+        save_png(&path, width, height, &pixels)
+            .map(|_| path)
+            .map_err(|error| PngError(error.to_string()))
+    })
+    .await
+    .expect("Blocking task to finish")
+}
+
+struct PaintApp {
+    image: Option<GeneratedImage>,
+    job: Option<Job>,
+    next_job_id: u64,
+    view: Option<ViewTransform>, // None: ajustada a la ventana
+    canvas_size: Size,
+    region: Region,
+    hover: Option<Hover>,
+    probes: Vec<[f64; 2]>, // sondas fijadas, en coordenadas del plano de ruido
+    auto_regenerate: bool,
+    last_params: Option<(NoiseParams, PostProcess, Option<MoistureField>, Option<RiverSettings>)>, // los de la última generación lanzada
+    debounce: u64,
+    export_path: String,
+    exporting: bool,
+    export_result: Option<Result<String, PngError>>,
+    noise_kind: NoiseKind,
+    fractal_kind: FractalKind,
+    seed: u32,
+    seed_input: String,
+    seed_locked: bool,
+    gradient: Gradient,
+    gradient_preset: Option<GradientPreset>, // None si las paradas se han editado a mano
+    normalization: Normalization,
+    threshold: f64, // umbral del panel de estadísticas, en valor crudo
+    stop_inputs: Vec<String>, // texto hexadecimal de cada parada mientras se edita
+    octaves: BoundedParam,
+    lacunarity: ScaledBoundedParam,
+    persistence: ScaledBoundedParam,
+    frequency: ScaledBoundedParam,
+    amplitude: ScaledBoundedParam,
+    offset: ScaledBoundedParam,
+    gain: ScaledBoundedParam,
+    tileable: bool,
+    tile_preview: bool, // muestra la imagen repetida 2x2
+    warp_enabled: bool,
+    warp_iterated: bool, // aplica también el segundo campo sobre el primero
+    warp: [WarpControls; 2],
+    img_width: BoundedParam,
+    img_height: BoundedParam,
+    post: PostProcess,
+    compare: f32, // fracción de la imagen, desde la izquierda, que se ve sin postprocesar
+    biomes: BiomeSettings,
+    rivers: RiverSettings,
+    river_overlay: bool, // dibuja los ríos sobre la imagen
+    river_svg_path: String,
+    river_status: Option<Result<String, String>>,
+    layers: Vec<Layer>, // siempre al menos una; los controles de ruido editan la seleccionada
+    selected_layer: usize,
+    presets: Vec<(PathBuf, Result<Preset, String>)>, // contenido de presets/
+    preset_name: String,
+    active_preset: Option<PathBuf>,
+    preset_status: Option<Result<String, String>>,
+    committed: Preset, // estado tras el último paso del historial
+    undo: VecDeque<Preset>,
+    redo: Vec<Preset>,
+    last_edit: Option<(Discriminant<Message>, Instant)>,
+    keep_thumbnails: bool,
+    thumbnails: VecDeque<(Preset, Handle)>, // la más reciente primero
+    graph_mode: bool, // el lienzo muestra el editor de nodos en lugar de la imagen
+    graph: Graph, // vacío hasta que se abre el editor
+    selected_node: Option<NodeId>,
+    graph_status: Option<Result<String, String>>,
+    generation_error: Option<String>, // p. ej. una máscara que no se puede leer
+}
+
+impl Default for PaintApp {
+    fn default() -> Self {
+        PaintApp {
+            image: None,
+            job: None,
+            next_job_id: 0,
+            view: None,
+            canvas_size: Size::ZERO,
+            region: Region::default(),
+            hover: None,
+            probes: Vec::new(),
+            auto_regenerate: false,
+            last_params: None,
+            debounce: 0,
+            export_path: String::from("ruido.png"),
+            exporting: false,
+            export_result: None,
+            noise_kind: NoiseKind::Perlin,
+            fractal_kind: FractalKind::Fbm,
+            seed: 0,
+            seed_input: String::from("0"),
+            seed_locked: false,
+            gradient: Gradient::preset(GradientPreset::Grayscale),
+            gradient_preset: Some(GradientPreset::Grayscale),
+            normalization: Normalization::default(),
+            threshold: 0.0,
+            stop_inputs: stop_inputs(&Gradient::preset(GradientPreset::Grayscale)),
+            octaves: BoundedParam { val: 8, min: 1, max: 20, step: 1 },
+            lacunarity: ScaledBoundedParam { val: 20, min: 1, max: 40, step: 1, scale: 10.0 },
+            persistence: ScaledBoundedParam { val: 50, min: 1, max: 100, step: 1, scale: 100.0 },
+            frequency: ScaledBoundedParam { val: 50, min: 1, max: 10000, step: 1, scale: 100.0 },
+            amplitude: ScaledBoundedParam { val: 50, min: 1, max: 1000, step: 1, scale: 100.0 },
+            offset: ScaledBoundedParam { val: 100, min: 0, max: 200, step: 5, scale: 100.0 },
+            gain: ScaledBoundedParam { val: 200, min: 0, max: 600, step: 10, scale: 100.0 },
+            tileable: false,
+            tile_preview: false,
+            warp_enabled: false,
+            warp_iterated: false,
+            warp: Default::default(),
+            img_width: BoundedParam { val: 1000, min: 50, max: 2000, step: 100 },
+            img_height: BoundedParam { val: 600, min: 50, max: 2000, step: 100 },
+            post: PostProcess::default(),
+            compare: 0.0,
+            biomes: BiomeSettings::default(),
+            rivers: RiverSettings::default(),
+            river_overlay: true,
+            river_svg_path: String::from("rios.svg"),
+            river_status: None,
+            layers: vec![Layer::new("Capa 1", NoiseParams::default())],
+            selected_layer: 0,
+            presets: Vec::new(),
+            preset_name: String::new(),
+            active_preset: None,
+            preset_status: None,
+            committed: Preset::default(),
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            last_edit: None,
+            keep_thumbnails: true,
+            thumbnails: VecDeque::new(),
+            graph_mode: false,
+            graph: Graph::default(),
+            selected_node: None,
+            graph_status: None,
+            generation_error: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Message {
+    Clear,
+    ApplyTestImage,
+    CancelGeneration,
+    Job(u64, JobEvent),
+    ViewChanged(ViewTransform, Size),
+    FitToWindow,
+    ResampleView,
+    ResetRegion,
+    Hovered(Option<Hover>),
+    PinProbe(Point),
+    RemoveProbe(usize),
+    ClearProbes,
+    AutoRegenerateToggled(bool),
+    DebounceElapsed(u64),
+    ExportPathChanged(String),
+    Export,
+    Exported(Result<String, PngError>),
+    NoiseKindChanged(NoiseKind),
+    FractalKindChanged(FractalKind),
+    SeedInputChanged(String),
+    RandomizeSeed,
+    SeedLockToggled(bool),
+    GradientPresetSelected(GradientPreset),
+    GradientBandsToggled(bool),
+    ThresholdChanged(f64),
+    NormalizeModeSelected(NormalizeMode),
+    NormalizeMinChanged(f64),
+    NormalizeMaxChanged(f64),
+    NormalizeLowChanged(f64),
+    NormalizeHighChanged(f64),
+    StopPositionChanged(usize, u32),
+    StopColorChanged(usize, String),
+    AddStop,
+    RemoveStop(usize),
+    OctavesChanged(u32),
+    LacunarityChanged(u32),
+    PersistenceChanged(u32),
+    DAmplitudeChanged(u32),
+    DFrequencyChanged(u32),
+    OffsetChanged(u32),
+    TileableToggled(bool),
+    TilePreviewToggled(bool),
+    WarpToggled(bool),
+    WarpIteratedToggled(bool),
+    WarpOctavesChanged(usize, u32),
+    WarpFrequencyChanged(usize, u32),
+    WarpStrengthChanged(usize, u32),
+    GainChanged(u32),
+    ImgWidthChanged(u32),
+    ImgHeightChanged(u32),
+    MaskToggled(bool),
+    MaskShapeSelected(MaskShape),
+    MaskModeSelected(MaskMode),
+    MaskSharpnessChanged(f64),
+    MaskOffsetChanged(f64),
+    MaskPointDistanceChanged(usize, f64),
+    MaskPointValueChanged(usize, f64),
+    AddMaskPoint,
+    RemoveMaskPoint(usize),
+    MaskImageChanged(String),
+    HydraulicChanged(HydraulicErosion),
+    ThermalChanged(ThermalErosion),
+    CompareChanged(f32),
+    RiversChanged(RiverSettings),
+    RiverOverlayToggled(bool),
+    RiverSvgPathChanged(String),
+    ExportRiverSvg,
+    BiomesToggled(bool),
+    MoistureChanged(MoistureField),
+    BiomeLatitudeToggled(bool),
+    BiomeTemperatureChanged(f64),
+    BiomeLapseChanged(f64),
+    WaterLevelChanged(f64),
+    WaterBiomeSelected(String),
+    BiomeCellSelected(usize, usize, String), // fila, columna y bioma
+    PresetNameChanged(String),
+    SavePreset,
+    LoadPreset(PathBuf),
+    RefreshPresets,
+    SelectLayer(usize),
+    AddLayer,
+    DuplicateLayer,
+    RemoveLayer(usize),
+    MoveLayer(usize, bool), // true: hacia arriba
+    LayerVisibleToggled(usize, bool),
+    LayerSoloToggled(usize, bool),
+    LayerNameChanged(String),
+    LayerBlendSelected(BlendMode),
+    LayerOpacityChanged(f64),
+    Undo,
+    Redo,
+    ThumbnailsToggled(bool),
+    RestoreThumbnail(usize),
+    GraphModeToggled(bool),
+    AddGraphNode(NodeKind),
+    SelectGraphNode(Option<NodeId>),
+    MoveGraphNode(NodeId, Point),
+    ConnectGraph(Wire),
+    DisconnectGraph(NodeId, usize),
+    RemoveGraphNode(NodeId),
+    GraphNodeChanged(NodeId, NodeKind),
+    RandomizeNodeSeed(NodeId),
+    EvaluateGraph,
+}
+
+impl Message {
+    // Si puede cambiar lo que guarda el historial. Los que llegan a cada
+    // momento (cursor, progreso, vista) o sólo tocan la interfaz no lo hacen
+    fn is_edit(&self) -> bool {
+        !matches!(
+            self,
+            Message::CancelGeneration
+                | Message::Job(..)
+                | Message::ViewChanged(..)
+                | Message::FitToWindow
+                | Message::Hovered(_)
+                | Message::PinProbe(_)
+                | Message::RemoveProbe(_)
+                | Message::ClearProbes
+                | Message::AutoRegenerateToggled(_)
+                | Message::DebounceElapsed(_)
+                | Message::ExportPathChanged(_)
+                | Message::Export
+                | Message::Exported(_)
+                | Message::ThresholdChanged(_)
+                | Message::TilePreviewToggled(_)
+                | Message::CompareChanged(_)
+                | Message::RiverOverlayToggled(_)
+                | Message::RiverSvgPathChanged(_)
+                | Message::ExportRiverSvg
+                | Message::PresetNameChanged(_)
+                | Message::SavePreset
+                | Message::RefreshPresets
+                | Message::ThumbnailsToggled(_)
+                | Message::SelectGraphNode(_)
+                | Message::EvaluateGraph
+        )
+    }
+}
+
+fn color_swatch<'a>([r, g, b]: [u8; 3]) -> Element<'a, Message> {
+    container(text(""))
+        .width(16)
+        .height(16)
+        .style(move |_| container::Style::default().background(Color::from_rgb8(r, g, b)))
+        .into()
+}
+
+// Ctrl+Z deshace; Ctrl+Shift+Z y Ctrl+Y rehacen
+fn shortcut(event: keyboard::Event) -> Option<Message> {
+    let keyboard::Event::KeyPressed { key, modifiers, .. } = event else { return None };
+    let keyboard::Key::Character(c) = key.as_ref() else { return None };
+    if !modifiers.command() {
+        return None;
+    }
+    match c.to_ascii_lowercase().as_str() {
+        "z" if modifiers.shift() => Some(Message::Redo),
+        "z" => Some(Message::Undo),
+        "y" => Some(Message::Redo),
+        _ => None,
+    }
+}
+
+// Mensaje que cambia un campo de los parámetros de un nodo de ruido
+fn noise_field<T>(id: NodeId, params: &NoiseParams, set: fn(&mut NoiseParams, T)) -> impl Fn(T) -> Message {
+    let params = params.clone();
+    move |value| {
+        let mut params = params.clone();
+        set(&mut params, value);
+        Message::GraphNodeChanged(id, NodeKind::Noise { params })
+    }
+}
+
+fn stop_inputs(gradient: &Gradient) -> Vec<String> {
+    gradient.stops.iter().map(|stop| palette::to_hex(stop.color)).collect()
+}
+
+impl PaintApp {
+    // Arranca con el último preset usado, si lo hay
+    fn new() -> (Self, Task<Message>) {
+        let mut app = PaintApp { presets: preset::list(preset::PRESET_DIR), ..PaintApp::default() };
+        let task = match preset::last_used(preset::PRESET_DIR) {
+            Some(path) => app.load_preset(path),
+            None => Task::none(),
+        };
+        app.committed = app.snapshot();
+        (app, task)
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        keyboard::listen().filter_map(shortcut)
+    }
+
+    // Lo que guarda el historial: todo lo del preset salvo el nombre
+    fn snapshot(&self) -> Preset {
+        Preset { name: String::new(), ..self.current_preset() }
+    }
+
+    fn record_history(&mut self, edit: Discriminant<Message>) {
+        let current = self.snapshot();
+        if current == self.committed {
+            return;
+        }
+
+        let now = Instant::now();
+        let same_gesture = self.last_edit.is_some_and(|(last, at)| last == edit && now - at < COALESCE);
+        let previous = std::mem::replace(&mut self.committed, current);
+        if !same_gesture {
+            self.undo.push_back(previous);
+            if self.undo.len() > HISTORY_LIMIT {
+                self.undo.pop_front();
+            }
+        }
+        self.redo.clear();
+        self.last_edit = Some((edit, now));
+    }
+
+    // Vuelve a un estado del historial y, si había imagen, la regenera
+    fn restore(&mut self, snapshot: Preset) -> Task<Message> {
+        self.apply_preset(snapshot.clone());
+        self.committed = snapshot;
+        self.last_edit = None;
+        if self.image.is_some() && !self.auto_regenerate {
+            self.regenerate()
+        } else {
+            Task::none()
+        }
+    }
+
+    fn push_thumbnail(&mut self) {
+        let Some(image) = &self.image else { return };
+        let Some(rgba) = ::image::RgbaImage::from_raw(image.width, image.height, image.pixels.clone()) else { return };
+
+        let scale = THUMBNAIL_SIZE as f32 / image.width.max(image.height) as f32;
+        let width = ((image.width as f32 * scale) as u32).max(1);
+        let height = ((image.height as f32 * scale) as u32).max(1);
+        let small = ::image::imageops::thumbnail(&rgba, width, height);
+
+        self.thumbnails.push_front((image.preset.clone(), Handle::from_rgba(width, height, small.into_raw())));
+        self.thumbnails.truncate(THUMBNAIL_LIMIT);
+    }
+
+    fn current_preset(&self) -> Preset {
+        Preset {
+            name: self.preset_name.trim().to_string(),
+            params: self.noise_params(),
+            gradient: self.gradient.clone(),
+            normalize: self.normalization,
+            post: self.post.clone(),
+            biomes: self.biomes.clone(),
+            rivers: self.rivers,
+            graph: (!self.graph.nodes.is_empty()).then(|| self.graph.clone()),
+        }
+    }
+
+    // Lleva los valores del preset a los controles
+    fn apply_preset(&mut self, preset: Preset) {
+        let params = preset.params;
+        self.tileable = params.tileable;
+        self.img_width.set(params.width);
+        self.img_height.set(params.height);
+        self.region = params.region;
+        self.view = None;
+
+        self.layers = if params.layers.is_empty() {
+            vec![Layer::new("Capa 1", params)]
+        } else {
+            params.layers
+        };
+        self.selected_layer = self.selected_layer.min(self.layers.len() - 1);
+        self.load_layer_controls(self.layers[self.selected_layer].params.clone());
+
+        self.gradient_preset = GradientPreset::ALL.into_iter()
+            .find(|&p| Gradient::preset(p).stops == preset.gradient.stops);
+        // Los biomas antes de repintar con la paleta y la normalización
+        self.biomes = preset.biomes;
+        self.set_gradient(preset.gradient);
+        self.set_normalization(preset.normalize);
+        self.post = preset.post;
+        self.rivers = preset.rivers;
+        self.graph = preset.graph.unwrap_or_default();
+        self.selected_node = self.selected_node.filter(|&id| self.graph.node(id).is_some());
+        if !preset.name.is_empty() {
+            self.preset_name = preset.name;
+        }
+    }
+
+    // Controles de ruido con los valores de una capa
+    fn load_layer_controls(&mut self, params: NoiseParams) {
+        self.noise_kind = params.noise;
+        self.fractal_kind = params.fractal;
+        self.octaves.set(params.octaves);
+        self.lacunarity.set_scaled(params.lacunarity);
+        self.persistence.set_scaled(params.persistence);
+        self.frequency.set_scaled(params.frequency);
+        self.amplitude.set_scaled(params.amplitude);
+        self.offset.set_scaled(params.offset);
+        self.gain.set_scaled(params.gain);
+        self.set_seed(params.seed);
+        self.warp_enabled = !params.warp.is_empty();
+        self.warp_iterated = params.warp.len() > 1;
+        for (controls, field) in self.warp.iter_mut().zip(&params.warp) {
+            controls.octaves.set(field.octaves);
+            controls.frequency.set_scaled(field.frequency);
+            controls.strength.set_scaled(field.strength);
+        }
+    }
+
+    // Guarda los controles en la capa seleccionada antes de cambiar de capa
+    fn store_selected_layer(&mut self) {
+        self.layers[self.selected_layer].params = self.layer_params();
+    }
+
+    fn select_layer(&mut self, index: usize) {
+        self.store_selected_layer();
+        self.selected_layer = index;
+        self.load_layer_controls(self.layers[index].params.clone());
+    }
+
+    fn load_preset(&mut self, path: PathBuf) -> Task<Message> {
+        match Preset::load(&path) {
+            Ok(loaded) => {
+                self.apply_preset(loaded);
+                // No poder recordarlo no impide usar el preset
+                self.preset_status = preset::set_last_used(preset::PRESET_DIR, &path).err().map(Err);
+                self.active_preset = Some(path);
+                self.regenerate()
+            },
+            Err(error) => {
+                self.preset_status = Some(Err(error));
+                Task::none()
+            },
+        }
+    }
 
-    for _ in 0..octaves {
-        total += perlin.get([pos[0] * frequency, pos[1] * frequency]) * amplitude;
+    fn save_preset(&mut self) {
+        let result = self.current_preset().save(preset::PRESET_DIR).and_then(|path| {
+            preset::set_last_used(preset::PRESET_DIR, &path)?;
+            self.active_preset = Some(path.clone());
+            Ok(format!("Preset guardado en {}", path.display()))
+        });
+        self.preset_status = Some(result);
+        self.presets = preset::list(preset::PRESET_DIR);
+    }
+
+    fn noise_params(&self) -> NoiseParams {
+        let current = self.layer_params();
+        // Una sola capa normal es lo mismo que no tener capas
+        if let [layer] = self.layers.as_slice() {
+            if layer.visible && layer.opacity >= 1.0 {
+                return current;
+            }
+        }
+
+        let layers = self.layers.iter().enumerate()
+            .map(|(index, layer)| {
+                if index == self.selected_layer {
+                    Layer { params: current.clone(), ..layer.clone() }
+                } else {
+                    layer.clone()
+                }
+            })
+            .collect();
+        NoiseParams { layers, ..current }
+    }
+
+    // Parámetros de la capa seleccionada según los controles
+    fn layer_params(&self) -> NoiseParams {
+        NoiseParams {
+            noise: self.noise_kind,
+            fractal: self.fractal_kind,
+            octaves: self.octaves.val,
+            lacunarity: self.lacunarity.scale(),
+            persistence: self.persistence.scale(),
+            frequency: self.frequency.scale(),
+            amplitude: self.amplitude.scale(),
+            offset: self.offset.scale(),
+            gain: self.gain.scale(),
+            tileable: self.tileable,
+            warp: self.warp_fields(),
+            layers: Vec::new(),
+            seed: self.seed,
+            width: self.img_width.val,
+            height: self.img_height.val,
+            region: self.region,
+        }
+    }
+
+    fn warp_fields(&self) -> Vec<WarpField> {
+        let count = match (self.warp_enabled, self.warp_iterated) {
+            (false, _) => 0,
+            (true, false) => 1,
+            (true, true) => 2,
+        };
+        self.warp[..count].iter().map(WarpControls::field).collect()
+    }
+
+    fn tiles(&self) -> u32 {
+        if self.tile_preview { 2 } else { 1 }
+    }
+
+    fn effective_view(&self, canvas: Size, image: &GeneratedImage) -> ViewTransform {
+        let tiles = self.tiles();
+        self.view.unwrap_or_else(|| {
+            ViewTransform::fit(canvas, image.display_width * tiles, image.display_height * tiles)
+        })
+    }
+
+    // En el mosaico cualquier copia remite a la imagen original
+    fn tile_point(&self, image: &GeneratedImage, point: Point) -> Point {
+        let (w, h) = (image.display_width as f32, image.display_height as f32);
+        let tiles = self.tiles() as f32;
+        if point.x < 0.0 || point.y < 0.0 || point.x >= w * tiles || point.y >= h * tiles {
+            return point;
+        }
+        Point::new(point.x % w, point.y % h)
+    }
+
+    // Zona del plano de ruido que se ve ahora en el lienzo, con la proporción de la imagen
+    fn visible_region(&self) -> Option<Region> {
+        let (image, view) = (self.image.as_ref()?, self.view?);
+        let top_left = view.to_image(Point::ORIGIN);
+        let bottom_right = view.to_image(Point::new(self.canvas_size.width, self.canvas_size.height));
+
+        let (dw, dh) = (image.display_width as f64, image.display_height as f64);
+        let mut w = (bottom_right.x - top_left.x) as f64;
+        let mut h = (bottom_right.y - top_left.y) as f64;
+        if w / h > dw / dh {
+            h = w * dh / dw;
+        } else {
+            w = h * dw / dh;
+        }
+        let cx = (top_left.x + bottom_right.x) as f64 / 2.0;
+        let cy = (top_left.y + bottom_right.y) as f64 / 2.0;
+
+        let region = image.preset.params.region;
+        Some(Region {
+            x: region.x + (cx - w / 2.0) / dw * region.width,
+            y: region.y + (cy - h / 2.0) / dh * region.height,
+            width: region.width * w / dw,
+            height: region.height * h / dh,
+        })
+    }
+
+    fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.seed_input = seed.to_string();
+    }
+
+    fn set_gradient(&mut self, gradient: Gradient) {
+        self.stop_inputs = stop_inputs(&gradient);
+        self.gradient = gradient;
+        self.recolor();
+    }
+
+    // Repinta la imagen actual sin volver a generar el ruido
+    fn recolor(&mut self) {
+        if let Some(image) = &mut self.image {
+            image.recolor(&self.gradient, &self.biomes);
+        }
+    }
+
+    fn set_normalization(&mut self, normalization: Normalization) {
+        self.normalization = normalization;
+        if let Some(image) = &mut self.image {
+            image.renormalize(&self.gradient, &self.normalization, &self.biomes);
+        }
+    }
+
+    // Lanza una generación nueva; la que hubiera en curso queda cancelada
+    fn start_generation(&mut self, preview: bool) -> Task<Message> {
+        self.cancel_generation();
+
+        let id = self.next_job_id;
+        self.next_job_id += 1;
+        let params = self.noise_params();
+        let mut job_params = params.clone();
+        let mut job_post = self.post.clone();
+        if preview {
+            job_params.width = (params.width / PREVIEW_DIVISOR).max(1);
+            job_params.height = (params.height / PREVIEW_DIVISOR).max(1);
+            job_post = self.post.scaled(1.0 / PREVIEW_DIVISOR as f64);
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (moisture, rivers) = (self.moisture_field(), self.river_settings());
+        let stream = apply_perlin(job_params, job_post, moisture, rivers, cancel.clone());
+        self.last_params = Some((params.clone(), self.post.clone(), moisture, rivers));
+        self.generation_error = None;
+        let (post, preset) = (self.post.clone(), self.snapshot());
+        self.job = Some(Job { id, params, post, moisture, rivers, graph: None, preset, preview, cancel, progress: 0.0 });
+
+        Task::run(stream, move |event| Message::Job(id, event))
+    }
+
+    // Como `start_generation`, pero evaluando el grafo con el tamaño y la zona de los controles
+    fn start_graph_evaluation(&mut self) -> Task<Message> {
+        self.cancel_generation();
+
+        let id = self.next_job_id;
+        self.next_job_id += 1;
+        let params = self.noise_params();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let stream = apply_graph(self.graph.clone(), params.clone(), cancel.clone());
+        self.graph_status = None;
+        let (post, preset) = (self.post.clone(), self.snapshot());
+        let graph = Some(self.graph.clone());
+        self.job = Some(Job { id, params, post, moisture: None, rivers: None, graph, preset, preview: false, cancel,
+                              progress: 0.0 });
+
+        Task::run(stream, move |event| Message::Job(id, event))
+    }
+
+    // Campo de humedad que necesita la generación, sólo con los biomas activos
+    fn moisture_field(&self) -> Option<MoistureField> {
+        self.biomes.enabled.then_some(self.biomes.moisture)
+    }
+
+    fn river_settings(&self) -> Option<RiverSettings> {
+        self.rivers.enabled.then_some(self.rivers)
+    }
+
+    // Vuelve a calcular la imagen por el camino que esté en uso
+    fn regenerate(&mut self) -> Task<Message> {
+        if self.graph_mode {
+            self.start_graph_evaluation()
+        } else {
+            self.start_generation(false)
+        }
+    }
+
+    // Vista previa inmediata y generación completa cuando los parámetros dejan de cambiar
+    fn start_preview(&mut self) -> Task<Message> {
+        self.debounce += 1;
+        let token = self.debounce;
+
+        Task::batch([
+            self.start_generation(true),
+            Task::perform(tokio::time::sleep(DEBOUNCE), move |_| Message::DebounceElapsed(token)),
+        ])
+    }
+
+    fn cancel_generation(&mut self) {
+        if let Some(job) = self.job.take() {
+            job.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn update(&mut self, message: Message) -> Task<Message> {
+        // El resto de mensajes no cambia los parámetros: ni historial ni regenerar
+        let Some(edit) = message.is_edit().then(|| std::mem::discriminant(&message)) else {
+            return self.handle(message);
+        };
+        let restoring = matches!(message, Message::Undo | Message::Redo | Message::RestoreThumbnail(_));
+        let task = self.handle(message);
+        if !restoring {
+            self.record_history(edit);
+        }
+        let params = self.noise_params();
+
+        let current = (params.clone(), self.post.clone(), self.moisture_field(), self.river_settings());
+        if self.auto_regenerate && !self.graph_mode && self.last_params.as_ref() != Some(&current) {
+            return Task::batch([task, self.start_preview()]);
+        }
+
+        // Si los parámetros cambian a mitad de una generación, se sustituye por una nueva
+        match &self.job {
+            Some(Job { graph: Some(graph), params: job_params, .. }) => {
+                if *graph != self.graph || *job_params != params {
+                    Task::batch([task, self.start_graph_evaluation()])
+                } else {
+                    task
+                }
+            },
+            Some(job) if job.params != params || job.post != self.post || job.moisture != self.moisture_field()
+                || job.rivers != self.river_settings() => {
+                let preview = job.preview;
+                Task::batch([task, self.start_generation(preview)])
+            },
+            _ => task,
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::Clear => {
+                // Si no, la generación en curso volvería a poner una imagen
+                self.cancel_generation();
+                self.image = None;
+            },
+            Message::ApplyTestImage => {
+                if !self.seed_locked {
+                    self.set_seed(rand::thread_rng().gen());
+                }
+                return self.start_generation(false);
+            },
+            Message::CancelGeneration => self.cancel_generation(),
+            Message::ViewChanged(view, canvas_size) => {
+                self.view = Some(view);
+                self.canvas_size = canvas_size;
+            },
+            Message::FitToWindow => self.view = None,
+            Message::ResampleView => {
+                if let Some(region) = self.visible_region() {
+                    self.region = region;
+                    self.view = None;
+                    return self.start_generation(false);
+                }
+            },
+            Message::ResetRegion => {
+                self.region = Region::default();
+                self.view = None;
+                return self.start_generation(false);
+            },
+            Message::Hovered(hover) => self.hover = hover,
+            Message::PinProbe(point) => {
+                if let Some(image) = &self.image {
+                    self.probes.push(image.sample_point(point));
+                }
+            },
+            Message::RemoveProbe(index) => {
+                self.probes.remove(index);
+            },
+            Message::ClearProbes => self.probes.clear(),
+            Message::AutoRegenerateToggled(auto) => self.auto_regenerate = auto,
+            Message::DebounceElapsed(token) => {
+                if token == self.debounce {
+                    return self.start_generation(false);
+                }
+            },
+            Message::Job(id, event) => {
+                let Some(job) = self.job.as_mut().filter(|job| job.id == id) else {
+                    return Task::none(); // resultado de una generación ya sustituida
+                };
+                match event {
+                    JobEvent::Progress(progress) => job.progress = progress,
+                    JobEvent::Finished(generated) => {
+                        let (preset, preview) = (job.preset.clone(), job.preview);
+                        self.job = None;
+                        let mut image = GeneratedImage::new(generated.heightmap, preset, &self.gradient, &self.normalization);
+                        if let Some(before) = generated.before {
+                            image = image.with_before(before, &self.gradient);
+                        }
+                        if let Some(moisture) = generated.moisture {
+                            image = image.with_moisture(moisture, &self.gradient, &self.biomes);
+                        }
+                        image.rivers = generated.rivers;
+                        image.preview = preview;
+                        self.image = Some(image);
+                        if self.keep_thumbnails && !preview {
+                            self.push_thumbnail();
+                        }
+                    },
+                    JobEvent::Evaluated(evaluation) => {
+                        let preset = job.preset.clone();
+                        self.job = None;
+                        if let Some(heightmap) = evaluation.image {
+                            let image = GeneratedImage::new(heightmap, preset, &self.gradient, &self.normalization);
+                            self.image = Some(GeneratedImage { from_graph: true, ..image });
+                            if self.keep_thumbnails {
+                                self.push_thumbnail();
+                            }
+                        }
+                        return self.export_graph(evaluation.exports);
+                    },
+                    JobEvent::Failed(error) => {
+                        if job.graph.is_some() {
+                            self.graph_status = Some(Err(error));
+                        } else {
+                            self.generation_error = Some(error);
+                        }
+                        self.job = None;
+                    },
+                }
+            },
+            Message::ExportPathChanged(path) => self.export_path = path,
+            Message::Export => {
+                if let Some(image) = self.image.as_ref().filter(|image| !image.preview) {
+                    self.exporting = true;
+                    self.export_result = None;
+
+                    return Task::perform(
+                        save_to_png(self.export_path.clone(), image.width, image.height, image.pixels.clone()),
+                        Message::Exported,
+                    );
+                }
+            },
+            Message::Exported(result) => {
+                self.exporting = false;
+                self.export_result = Some(result);
+            },
+            Message::NoiseKindChanged(kind) => self.noise_kind = kind,
+            Message::FractalKindChanged(kind) => self.fractal_kind = kind,
+            Message::SeedInputChanged(input) => {
+                // Escribir una semilla a mano implica querer conservarla. Sólo se
+                // acepta lo que cabe en un u32, para que el campo muestre la
+                // semilla que se usa; vacío se deja pasar para poder borrarlo.
+                let digits = input.chars().all(|c| c.is_ascii_digit());
+                match input.parse() {
+                    Ok(seed) if digits => {
+                        self.seed = seed;
+                        self.seed_locked = true;
+                        self.seed_input = input;
+                    },
+                    _ if input.is_empty() => self.seed_input = input,
+                    _ => {},
+                }
+            },
+            Message::RandomizeSeed => self.set_seed(rand::thread_rng().gen()),
+            Message::SeedLockToggled(locked) => self.seed_locked = locked,
+            Message::GradientPresetSelected(preset) => {
+                self.gradient_preset = Some(preset);
+                let mode = self.gradient.mode;
+                self.set_gradient(Gradient { mode, ..Gradient::preset(preset) });
+            },
+            Message::GradientBandsToggled(bands) => {
+                self.gradient.mode = if bands { GradientMode::Bands } else { GradientMode::Smooth };
+                self.recolor();
+            },
+            Message::ThresholdChanged(threshold) => self.threshold = threshold,
+            Message::NormalizeModeSelected(mode) => {
+                self.set_normalization(Normalization { mode, ..self.normalization });
+            },
+            Message::NormalizeMinChanged(min) => {
+                self.set_normalization(Normalization { min: min.min(self.normalization.max - 0.05), ..self.normalization });
+            },
+            Message::NormalizeMaxChanged(max) => {
+                self.set_normalization(Normalization { max: max.max(self.normalization.min + 0.05), ..self.normalization });
+            },
+            Message::NormalizeLowChanged(low) => {
+                self.set_normalization(Normalization { low: low.min(self.normalization.high), ..self.normalization });
+            },
+            Message::NormalizeHighChanged(high) => {
+                self.set_normalization(Normalization { high: high.max(self.normalization.low), ..self.normalization });
+            },
+            Message::StopPositionChanged(index, val) => {
+                self.gradient.stops[index].position = val as f64 / 1000.0;
+                self.gradient_preset = None;
+                self.recolor();
+            },
+            Message::StopColorChanged(index, input) => {
+                if let Some(color) = palette::parse_hex(&input) {
+                    self.gradient.stops[index].color = color;
+                    self.gradient_preset = None;
+                    self.recolor();
+                }
+                self.stop_inputs[index] = input;
+            },
+            Message::AddStop => {
+                let mut gradient = self.gradient.clone();
+                gradient.add_stop();
+                self.gradient_preset = None;
+                self.set_gradient(gradient);
+            },
+            Message::RemoveStop(index) => {
+                let mut gradient = self.gradient.clone();
+                gradient.stops.remove(index);
+                self.gradient_preset = None;
+                self.set_gradient(gradient);
+            },
+            Message::OctavesChanged(val) => self.octaves.val = val,
+            Message::LacunarityChanged(val) => self.lacunarity.val = val,
+            Message::PersistenceChanged(val) => self.persistence.val = val,
+            Message::DAmplitudeChanged(val) => self.amplitude.val = val,
+            Message::DFrequencyChanged(val) => self.frequency.val = val,
+            Message::OffsetChanged(val) => self.offset.val = val,
+            Message::GainChanged(val) => self.gain.val = val,
+            Message::TileableToggled(tileable) => self.tileable = tileable,
+            Message::TilePreviewToggled(preview) => {
+                self.tile_preview = preview;
+                self.view = None;
+            },
+            Message::WarpToggled(enabled) => self.warp_enabled = enabled,
+            Message::WarpIteratedToggled(iterated) => self.warp_iterated = iterated,
+            Message::WarpOctavesChanged(index, val) => self.warp[index].octaves.val = val,
+            Message::WarpFrequencyChanged(index, val) => self.warp[index].frequency.val = val,
+            Message::WarpStrengthChanged(index, val) => self.warp[index].strength.val = val,
+            Message::ImgWidthChanged(val) => self.img_width.val = val,
+            Message::ImgHeightChanged(val) => self.img_height.val = val,
+            Message::MaskToggled(enabled) => self.post.mask.enabled = enabled,
+            Message::MaskShapeSelected(shape) => self.post.mask.shape = shape,
+            Message::MaskModeSelected(mode) => self.post.mask.mode = mode,
+            Message::MaskSharpnessChanged(sharpness) => self.post.mask.sharpness = sharpness,
+            Message::MaskOffsetChanged(offset) => self.post.mask.offset = offset,
+            Message::MaskPointDistanceChanged(index, distance) => self.post.mask.curve[index][0] = distance,
+            Message::MaskPointValueChanged(index, value) => self.post.mask.curve[index][1] = value,
+            Message::AddMaskPoint => self.post.mask.add_point(),
+            Message::RemoveMaskPoint(index) => {
+                self.post.mask.curve.remove(index);
+            },
+            Message::MaskImageChanged(path) => self.post.mask.image = path,
+            Message::HydraulicChanged(hydraulic) => self.post.hydraulic = hydraulic,
+            Message::ThermalChanged(thermal) => self.post.thermal = thermal,
+            Message::CompareChanged(compare) => self.compare = compare,
+            Message::RiversChanged(rivers) => self.rivers = rivers,
+            Message::RiverOverlayToggled(overlay) => self.river_overlay = overlay,
+            Message::RiverSvgPathChanged(path) => self.river_svg_path = path,
+            Message::ExportRiverSvg => {
+                if let Some(network) = self.image.as_ref().and_then(|image| image.rivers.as_ref()) {
+                    self.river_status = Some(network.save_svg(&self.river_svg_path).map(|_| self.river_svg_path.clone()));
+                }
+            },
+            Message::BiomesToggled(enabled) => {
+                self.biomes.enabled = enabled;
+                self.recolor();
+            },
+            Message::MoistureChanged(moisture) => self.biomes.moisture = moisture,
+            Message::BiomeLatitudeToggled(latitude) => {
+                self.biomes.latitude = latitude;
+                self.recolor();
+            },
+            Message::BiomeTemperatureChanged(temperature) => {
+                self.biomes.temperature = temperature;
+                self.recolor();
+            },
+            Message::BiomeLapseChanged(lapse) => {
+                self.biomes.lapse = lapse;
+                self.recolor();
+            },
+            Message::WaterLevelChanged(level) => {
+                self.biomes.water_level = level;
+                self.recolor();
+            },
+            Message::WaterBiomeSelected(name) => {
+                self.biomes.water = name;
+                self.recolor();
+            },
+            Message::BiomeCellSelected(row, column, name) => {
+                self.biomes.table[row][column] = name;
+                self.recolor();
+            },
+            Message::PresetNameChanged(name) => self.preset_name = name,
+            Message::SavePreset => self.save_preset(),
+            Message::LoadPreset(path) => return self.load_preset(path),
+            Message::RefreshPresets => self.presets = preset::list(preset::PRESET_DIR),
+            Message::SelectLayer(index) => self.select_layer(index),
+            Message::AddLayer => {
+                self.store_selected_layer();
+                let params = NoiseParams { seed: rand::thread_rng().gen(), ..self.layer_params() };
+                let name = format!("Capa {}", self.layers.len() + 1);
+                self.layers.push(Layer::new(name, params));
+                self.selected_layer = self.layers.len() - 1;
+                self.load_layer_controls(self.layers[self.selected_layer].params.clone());
+            },
+            Message::DuplicateLayer => {
+                self.store_selected_layer();
+                let mut copy = self.layers[self.selected_layer].clone();
+                copy.name = format!("{} (copia)", copy.name);
+                copy.solo = false;
+                self.selected_layer += 1;
+                self.layers.insert(self.selected_layer, copy);
+            },
+            Message::RemoveLayer(index) => {
+                if self.layers.len() > 1 {
+                    self.store_selected_layer();
+                    self.layers.remove(index);
+                    if self.selected_layer >= index {
+                        self.selected_layer = self.selected_layer.saturating_sub(1);
+                    }
+                    self.load_layer_controls(self.layers[self.selected_layer].params.clone());
+                }
+            },
+            Message::MoveLayer(index, up) => {
+                let other = if up { index + 1 } else { index.wrapping_sub(1) };
+                if other < self.layers.len() {
+                    self.store_selected_layer();
+                    self.layers.swap(index, other);
+                    if self.selected_layer == index {
+                        self.selected_layer = other;
+                    } else if self.selected_layer == other {
+                        self.selected_layer = index;
+                    }
+                }
+            },
+            Message::LayerVisibleToggled(index, visible) => self.layers[index].visible = visible,
+            Message::LayerSoloToggled(index, solo) => self.layers[index].solo = solo,
+            Message::LayerNameChanged(name) => self.layers[self.selected_layer].name = name,
+            Message::LayerBlendSelected(blend) => self.layers[self.selected_layer].blend = blend,
+            Message::LayerOpacityChanged(opacity) => self.layers[self.selected_layer].opacity = opacity,
+            Message::Undo => {
+                if let Some(previous) = self.undo.pop_back() {
+                    self.redo.push(self.snapshot());
+                    return self.restore(previous);
+                }
+            },
+            Message::Redo => {
+                if let Some(next) = self.redo.pop() {
+                    self.undo.push_back(self.snapshot());
+                    return self.restore(next);
+                }
+            },
+            Message::ThumbnailsToggled(keep) => {
+                self.keep_thumbnails = keep;
+                if !keep {
+                    self.thumbnails.clear();
+                }
+            },
+            Message::RestoreThumbnail(index) => {
+                if let Some((snapshot, _)) = self.thumbnails.get(index).cloned() {
+                    // Volver a una miniatura es un paso más, que también se puede deshacer
+                    self.undo.push_back(self.snapshot());
+                    self.redo.clear();
+                    return self.restore(snapshot);
+                }
+            },
+            Message::GraphModeToggled(enabled) => {
+                self.graph_mode = enabled;
+                if enabled && self.graph.nodes.is_empty() {
+                    self.graph = Graph::starter(&self.layer_params());
+                }
+            },
+            Message::AddGraphNode(kind) => {
+                // En escalera para que los nodos nuevos no queden uno encima de otro
+                let step = (self.graph.nodes.len() % 8) as f32 * 30.0;
+                self.selected_node = Some(self.graph.add(kind, [30.0 + step, 30.0 + step]));
+            },
+            Message::SelectGraphNode(id) => self.selected_node = id,
+            Message::MoveGraphNode(id, position) => {
+                if let Some(node) = self.graph.node_mut(id) {
+                    node.position = [position.x.max(0.0), position.y.max(0.0)];
+                }
+            },
+            Message::ConnectGraph(wire) => self.graph_status = self.graph.connect(wire).err().map(Err),
+            Message::DisconnectGraph(to, input) => self.graph.disconnect(to, input),
+            Message::RemoveGraphNode(id) => {
+                self.graph.remove(id);
+                self.selected_node = None;
+            },
+            Message::GraphNodeChanged(id, kind) => {
+                if let Some(node) = self.graph.node_mut(id) {
+                    node.kind = kind;
+                }
+            },
+            Message::RandomizeNodeSeed(id) => {
+                if let Some(Node { kind: NodeKind::Noise { params }, .. }) = self.graph.node_mut(id) {
+                    params.seed = rand::thread_rng().gen();
+                }
+            },
+            Message::EvaluateGraph => return self.start_graph_evaluation(),
+        }
+
+        Task::none()
+    }
+
+    // Guarda lo que ha llegado a cada nodo de exportar con la paleta actual
+    fn export_graph(&mut self, exports: Vec<(String, Heightmap)>) -> Task<Message> {
+        if exports.is_empty() {
+            return Task::none();
+        }
+        self.exporting = true;
+        self.export_result = None;
+        Task::batch(exports.into_iter().map(|(path, heightmap)| {
+            let normalizer = self.normalization.fit(&heightmap.data);
+            let pixels = heightmap.to_rgba(&self.gradient, &normalizer);
+            Task::perform(save_to_png(path, heightmap.width, heightmap.height, pixels), Message::Exported)
+        }))
+    }
+
+    fn warp_editor(&self) -> Element<'_, Message> {
+        let toggle = checkbox(self.warp_enabled)
+            .label("Deformar el dominio")
+            .on_toggle(Message::WarpToggled);
+        if !self.warp_enabled {
+            return toggle.into();
+        }
+
+        let iterated = checkbox(self.warp_iterated)
+            .label("Segundo campo sobre el primero")
+            .on_toggle(Message::WarpIteratedToggled);
+        let count = if self.warp_iterated { 2 } else { 1 };
+
+        let fields = self.warp[..count].iter().enumerate().map(|(index, warp)| {
+            column![
+                text(format!("Campo {}", index + 1)),
+                text(format!("Octavas: {}", warp.octaves.val)),
+                container(
+                    slider(warp.octaves.min ..= warp.octaves.max, warp.octaves.val,
+                           move |val| Message::WarpOctavesChanged(index, val))
+                        .shift_step(warp.octaves.step),
+                )
+                .width(250),
+                text(format!("Frecuencia: {}", warp.frequency.scale())),
+                container(
+                    slider(warp.frequency.min ..= warp.frequency.max, warp.frequency.val,
+                           move |val| Message::WarpFrequencyChanged(index, val))
+                        .shift_step(warp.frequency.step),
+                )
+                .width(250),
+                text(format!("Fuerza: {}", warp.strength.scale())),
+                container(
+                    slider(warp.strength.min ..= warp.strength.max, warp.strength.val,
+                           move |val| Message::WarpStrengthChanged(index, val))
+                        .shift_step(warp.strength.step),
+                )
+                .width(250),
+            ]
+            .spacing(6)
+            .into()
+        });
+
+        column![toggle, iterated]
+            .extend(fields)
+            .spacing(12)
+            .into()
+    }
+
+    // Pasos sobre el heightmap ya generado
+    fn post_process_editor(&self) -> Element<'_, Message> {
+        column![self.mask_editor(), self.hydraulic_editor(), self.thermal_editor()]
+            .spacing(12)
+            .into()
+    }
+
+    fn mask_editor(&self) -> Element<'_, Message> {
+        let mask = &self.post.mask;
+        let toggle = checkbox(mask.enabled)
+            .label("Máscara de borde")
+            .on_toggle(Message::MaskToggled);
+        if !mask.enabled {
+            return toggle.into();
+        }
+
+        // Vista de la máscara con la proporción de la imagen; la de fichero no se lee en cada repintado
+        let preview = (mask.shape != MaskShape::Image).then(|| {
+            let width = 120;
+            let height = ((width * self.img_height.val) / self.img_width.val).max(1);
+            let pixels: Vec<u8> = mask.values(width, height)
+                .unwrap_or_default()
+                .into_iter()
+                .flat_map(|m| {
+                    let v = (m * 255.0).round() as u8;
+                    [v, v, v, 255]
+                })
+                .collect();
+            iced::widget::image(Handle::from_rgba(width, height, pixels))
+        });
+
+        let shape_controls: Option<Element<'_, Message>> = match mask.shape {
+            MaskShape::Curve => {
+                let can_remove = mask.curve.len() > 2;
+                let points = mask.curve.iter().enumerate().map(|(index, &[distance, value])| {
+                    row![
+                        slider(0.0..=1.0, distance, move |d| Message::MaskPointDistanceChanged(index, d))
+                            .step(0.01)
+                            .width(100),
+                        slider(0.0..=1.0, value, move |v| Message::MaskPointValueChanged(index, v))
+                            .step(0.01)
+                            .width(100),
+                        button("x").on_press_maybe(can_remove.then_some(Message::RemoveMaskPoint(index))),
+                    ]
+                    .spacing(6)
+                    .into()
+                });
+                Some(
+                    column![text("Distancia al centro / valor:")]
+                        .extend(points)
+                        .push(button("Añadir punto").on_press(Message::AddMaskPoint))
+                        .spacing(6)
+                        .into(),
+                )
+            },
+            MaskShape::Image => Some(
+                text_input("mascara.png", &mask.image)
+                    .on_input(Message::MaskImageChanged)
+                    .width(250)
+                    .into(),
+            ),
+            MaskShape::Radial | MaskShape::Square => None,
+        };
+
+        column![
+            toggle,
+            row![
+                pick_list(&MaskShape::ALL[..], Some(mask.shape), Message::MaskShapeSelected),
+                pick_list(&MaskMode::ALL[..], Some(mask.mode), Message::MaskModeSelected),
+            ]
+            .spacing(8),
+        ]
+        .push(shape_controls)
+        .push(preview)
+        .push(text(format!("Nitidez del borde: {:.2}", mask.sharpness)))
+        .push(container(slider(0.1..=10.0, mask.sharpness, Message::MaskSharpnessChanged).step(0.05)).width(250))
+        .push(text(format!("Desplazamiento: {:.2}", mask.offset)))
+        .push(container(slider(-1.0..=1.0, mask.offset, Message::MaskOffsetChanged).step(0.01)).width(250))
+        .spacing(6)
+        .into()
+    }
+
+    fn hydraulic_editor(&self) -> Element<'_, Message> {
+        let hydraulic = self.post.hydraulic;
+        let toggle = checkbox(hydraulic.enabled)
+            .label("Erosión hidráulica")
+            .on_toggle(move |enabled| Message::HydraulicChanged(HydraulicErosion { enabled, ..hydraulic }));
+        if !hydraulic.enabled {
+            return toggle.into();
+        }
+
+        let changed = move |f: fn(&mut HydraulicErosion, f64)| move |value| {
+            let mut hydraulic = hydraulic;
+            f(&mut hydraulic, value);
+            Message::HydraulicChanged(hydraulic)
+        };
+        column![
+            toggle,
+            text(format!("Gotas: {}", hydraulic.droplets)),
+            container(slider(10_000..=1_000_000, hydraulic.droplets, move |droplets| {
+                Message::HydraulicChanged(HydraulicErosion { droplets, ..hydraulic })
+            }).step(10_000u32)).width(250),
+            text(format!("Radio: {}", hydraulic.radius)),
+            container(slider(1..=8, hydraulic.radius, move |radius| {
+                Message::HydraulicChanged(HydraulicErosion { radius, ..hydraulic })
+            })).width(250),
+            text(format!("Inercia: {:.2}", hydraulic.inertia)),
+            container(slider(0.0..=0.95, hydraulic.inertia, changed(|h, v| h.inertia = v)).step(0.01)).width(250),
+            text(format!("Capacidad de sedimento: {:.1}", hydraulic.capacity)),
+            container(slider(0.5..=16.0, hydraulic.capacity, changed(|h, v| h.capacity = v)).step(0.1)).width(250),
+            text(format!("Depósito: {:.2}", hydraulic.deposition)),
+            container(slider(0.0..=1.0, hydraulic.deposition, changed(|h, v| h.deposition = v)).step(0.01)).width(250),
+            text(format!("Erosión: {:.2}", hydraulic.erosion)),
+            container(slider(0.0..=1.0, hydraulic.erosion, changed(|h, v| h.erosion = v)).step(0.01)).width(250),
+            text(format!("Evaporación: {:.3}", hydraulic.evaporation)),
+            container(slider(0.0..=0.2, hydraulic.evaporation, changed(|h, v| h.evaporation = v)).step(0.001)).width(250),
+        ]
+        .spacing(6)
+        .into()
+    }
+
+    fn thermal_editor(&self) -> Element<'_, Message> {
+        let thermal = self.post.thermal;
+        let toggle = checkbox(thermal.enabled)
+            .label("Erosión térmica")
+            .on_toggle(move |enabled| Message::ThermalChanged(ThermalErosion { enabled, ..thermal }));
+        if !thermal.enabled {
+            return toggle.into();
+        }
+
+        column![
+            toggle,
+            text(format!("Pasadas: {}", thermal.iterations)),
+            container(slider(1..=500, thermal.iterations, move |iterations| {
+                Message::ThermalChanged(ThermalErosion { iterations, ..thermal })
+            })).width(250),
+            text(format!("Ángulo de reposo: {:.0}°", thermal.talus)),
+            container(slider(1.0..=89.0, thermal.talus, move |talus| {
+                Message::ThermalChanged(ThermalErosion { talus, ..thermal })
+            })).width(250),
+            text(format!("Fuerza: {:.2}", thermal.strength)),
+            container(slider(0.01..=0.5, thermal.strength, move |strength| {
+                Message::ThermalChanged(ThermalErosion { strength, ..thermal })
+            }).step(0.01)).width(250),
+        ]
+        .spacing(6)
+        .into()
+    }
+
+    fn river_editor(&self) -> Element<'_, Message> {
+        let rivers = self.rivers;
+        let toggle = checkbox(rivers.enabled)
+            .label("Ríos")
+            .on_toggle(move |enabled| Message::RiversChanged(RiverSettings { enabled, ..rivers }));
+        if !rivers.enabled {
+            return toggle.into();
+        }
+
+        let network = self.image.as_ref().and_then(|image| image.rivers.as_ref());
+        let status = self.river_status.as_ref().map(|status| match status {
+            Ok(path) => text(format!("Ríos guardados en {}", path)),
+            Err(error) => text(error.as_str()),
+        });
+
+        column![
+            toggle,
+            checkbox(self.river_overlay)
+                .label("Dibujar encima de la imagen")
+                .on_toggle(Message::RiverOverlayToggled),
+            text(format!("Cuenca mínima: {:.2}% de la imagen", rivers.threshold * 100.0)),
+            container(slider(0.0001..=0.02, rivers.threshold, move |threshold| {
+                Message::RiversChanged(RiverSettings { threshold, ..rivers })
+            }).step(0.0001)).width(250),
+            text(format!("Nivel del mar: {:.2}", rivers.sea_level)),
+            container(slider(0.0..=1.0, rivers.sea_level, move |sea_level| {
+                Message::RiversChanged(RiverSettings { sea_level, ..rivers })
+            }).step(0.01)).width(250),
+            checkbox(rivers.carve)
+                .label("Hundir los cauces")
+                .on_toggle(move |carve| Message::RiversChanged(RiverSettings { carve, ..rivers })),
+        ]
+        .push(rivers.carve.then(|| text(format!("Profundidad: {:.3}", rivers.depth))))
+        .push(rivers.carve.then(|| container(slider(0.0..=0.3, rivers.depth, move |depth| {
+            Message::RiversChanged(RiverSettings { depth, ..rivers })
+        }).step(0.005)).width(250)))
+        .push(network.map(|network| text(format!("{} tramos de río", network.rivers.len()))))
+        .push(row![
+            text_input("rios.svg", &self.river_svg_path)
+                .on_input(Message::RiverSvgPathChanged)
+                .width(150),
+            button("Exportar SVG").on_press_maybe(network.map(|_| Message::ExportRiverSvg)),
+        ].spacing(8))
+        .push(status)
+        .spacing(6)
+        .into()
+    }
+
+    // La humedad se genera con la imagen; el resto sólo repinta
+    fn biome_editor(&self) -> Element<'_, Message> {
+        let biomes = &self.biomes;
+        let toggle = checkbox(biomes.enabled)
+            .label("Mapa de biomas")
+            .on_toggle(Message::BiomesToggled);
+        if !biomes.enabled {
+            return toggle.into();
+        }
+
+        let moisture = biomes.moisture;
+        let names: Vec<String> = biomes.biomes.iter().map(|biome| biome.name.clone()).collect();
+        let temperature = (!biomes.latitude).then(|| column![
+            text(format!("Temperatura en la costa: {:.2}", biomes.temperature)),
+            container(slider(0.0..=1.0, biomes.temperature, Message::BiomeTemperatureChanged).step(0.01)).width(250),
+        ].spacing(6));
+
+        // Filas de frío a calor, columnas de seco a húmedo
+        let table = biomes.table.iter().enumerate().map(|(r, cells)| {
+            Row::with_children(cells.iter().enumerate().map(|(c, name)| {
+                pick_list(names.clone(), Some(name.clone()), move |name| Message::BiomeCellSelected(r, c, name))
+                    .text_size(12)
+                    .width(130)
+                    .into()
+            }))
+            .spacing(4)
+            .into()
+        });
+
+        column![
+            toggle,
+            text(format!("Nivel del agua: {:.2}", biomes.water_level)),
+            container(slider(0.0..=1.0, biomes.water_level, Message::WaterLevelChanged).step(0.01)).width(250),
+            row![
+                text("Bioma del agua:"),
+                pick_list(names.clone(), Some(biomes.water.clone()), Message::WaterBiomeSelected),
+            ]
+            .spacing(8),
+            checkbox(biomes.latitude)
+                .label("Temperatura según la latitud")
+                .on_toggle(Message::BiomeLatitudeToggled),
+        ]
+        .push(temperature)
+        .push(text(format!("Enfriamiento con la altura: {:.2}", biomes.lapse)))
+        .push(container(slider(0.0..=1.0, biomes.lapse, Message::BiomeLapseChanged).step(0.01)).width(250))
+        .push(text(format!("Frecuencia de la humedad: {:.2}", moisture.frequency)))
+        .push(container(slider(0.1..=10.0, moisture.frequency, move |frequency| {
+            Message::MoistureChanged(MoistureField { frequency, ..moisture })
+        }).step(0.05)).width(250))
+        .push(text(format!("Octavas de la humedad: {}", moisture.octaves)))
+        .push(container(slider(1..=12, moisture.octaves, move |octaves| {
+            Message::MoistureChanged(MoistureField { octaves, ..moisture })
+        })).width(250))
+        .push(text("Tabla (de frío a calor, de seco a húmedo):"))
+        .extend(table)
+        .push(biomes.validate().err().map(text))
+        .spacing(6)
+        .into()
+    }
+
+    fn history_controls(&self) -> Element<'_, Message> {
+        row![
+            button("Deshacer").on_press_maybe((!self.undo.is_empty()).then_some(Message::Undo)),
+            button("Rehacer").on_press_maybe((!self.redo.is_empty()).then_some(Message::Redo)),
+            text(format!("{} / {}", self.undo.len(), self.redo.len())),
+        ]
+        .spacing(8)
+        .into()
+    }
+
+    // Miniaturas de las últimas imágenes; al pulsar una se vuelve a sus parámetros
+    fn thumbnail_strip(&self) -> Option<Element<'_, Message>> {
+        if !self.keep_thumbnails || self.thumbnails.is_empty() {
+            return None;
+        }
+        let thumbnails = self.thumbnails.iter().enumerate().map(|(index, (_, handle))| {
+            button(iced::widget::image(handle.clone()).width(THUMBNAIL_SIZE as f32))
+                .on_press(Message::RestoreThumbnail(index))
+                .padding(2)
+                .into()
+        });
+        Some(
+            scrollable(row(thumbnails).spacing(6))
+                .direction(scrollable::Direction::Horizontal(scrollable::Scrollbar::new()))
+                .into(),
+        )
+    }
+
+    // Lista de capas, con la de arriba primero, y propiedades de la seleccionada
+    fn layer_panel(&self) -> Element<'_, Message> {
+        let count = self.layers.len();
+        let rows = self.layers.iter().enumerate().rev().map(|(index, layer)| {
+            let selected = index == self.selected_layer;
+            row![
+                button(text(layer.name.clone()))
+                    .on_press(Message::SelectLayer(index))
+                    .style(if selected { button::primary } else { button::secondary })
+                    .width(110),
+                checkbox(layer.visible)
+                    .label("Ver")
+                    .on_toggle(move |visible| Message::LayerVisibleToggled(index, visible)),
+                checkbox(layer.solo)
+                    .label("Solo")
+                    .on_toggle(move |solo| Message::LayerSoloToggled(index, solo)),
+                button("↑").on_press_maybe((index + 1 < count).then_some(Message::MoveLayer(index, true))),
+                button("↓").on_press_maybe((index > 0).then_some(Message::MoveLayer(index, false))),
+                button("x").on_press_maybe((count > 1).then_some(Message::RemoveLayer(index))),
+            ]
+            .spacing(6)
+            .align_y(iced::Alignment::Center)
+            .into()
+        });
+
+        let layer = &self.layers[self.selected_layer];
+        let properties = column![
+            text_input("Nombre de la capa", &layer.name)
+                .on_input(Message::LayerNameChanged)
+                .width(250),
+            row![
+                text("Mezcla:"),
+                pick_list(&BlendMode::ALL[..], Some(layer.blend), Message::LayerBlendSelected),
+            ]
+            .spacing(8)
+            .align_y(iced::Alignment::Center),
+            text(format!("Opacidad: {:.2}", layer.opacity)),
+            container(slider(0.0..=1.0, layer.opacity, Message::LayerOpacityChanged).step(0.01)).width(250),
+        ]
+        .spacing(8);
+
+        column![
+            row![
+                text("Capas:"),
+                button("Añadir").on_press(Message::AddLayer),
+                button("Duplicar").on_press(Message::DuplicateLayer),
+            ]
+            .spacing(8),
+        ]
+        .extend(rows)
+        .push(properties)
+        .spacing(8)
+        .into()
+    }
+
+    fn preset_browser(&self) -> Element<'_, Message> {
+        let save = row![
+            text_input("Nombre del preset", &self.preset_name)
+                .on_input(Message::PresetNameChanged)
+                .on_submit(Message::SavePreset)
+                .width(170),
+            button("Guardar").on_press_maybe(
+                preset::file_name(&self.preset_name).is_ok().then_some(Message::SavePreset)
+            ),
+        ]
+        .spacing(8);
+
+        let entries = self.presets.iter().map(|(path, loaded)| {
+            let active = self.active_preset.as_ref() == Some(path);
+            match loaded {
+                Ok(loaded) => {
+                    let label = if active { format!("> {}", loaded.name) } else { loaded.name.clone() };
+                    button(text(label))
+                        .on_press(Message::LoadPreset(path.clone()))
+                        .width(250)
+                        .style(if active { button::primary } else { button::secondary })
+                        .into()
+                },
+                Err(error) => text(error.clone()).width(250).into(),
+            }
+        });
+
+        let status = self.preset_status.as_ref().map(|status| match status {
+            Ok(message) => text(message.clone()),
+            Err(error) => text(error.clone()),
+        });
+
+        column![
+            row![text("Presets:"), button("Recargar").on_press(Message::RefreshPresets)].spacing(12),
+            save,
+        ]
+        .push(status)
+        .push(self.presets.is_empty().then(|| text(format!("No hay presets en {}/", preset::PRESET_DIR))))
+        .extend(entries)
+        .spacing(8)
+        .into()
+    }
+
+    fn normalization_editor(&self) -> Element<'_, Message> {
+        let normalization = &self.normalization;
+        let mode_list = pick_list(&NormalizeMode::ALL[..], Some(normalization.mode),
+                                  Message::NormalizeModeSelected);
+
+        let range = |label: String, range, value, on_change: fn(f64) -> Message| column![
+            text(label),
+            container(slider(range, value, on_change).step(0.01)).width(250),
+        ].spacing(6);
+        let params = match normalization.mode {
+            NormalizeMode::Fixed => Some(column![
+                range(format!("Mínimo: {:.2}", normalization.min), -2.0..=2.0, normalization.min,
+                      Message::NormalizeMinChanged),
+                range(format!("Máximo: {:.2}", normalization.max), -2.0..=2.0, normalization.max,
+                      Message::NormalizeMaxChanged),
+            ].spacing(12)),
+            NormalizeMode::Percentile => Some(column![
+                range(format!("Percentil bajo: {:.1}", normalization.low), 0.0..=50.0, normalization.low,
+                      Message::NormalizeLowChanged),
+                range(format!("Percentil alto: {:.1}", normalization.high), 50.0..=100.0, normalization.high,
+                      Message::NormalizeHighChanged),
+            ].spacing(12)),
+            NormalizeMode::MinMax | NormalizeMode::Equalize => None,
+        };
+
+        column![text("Normalización:"), mode_list]
+            .push(params)
+            .spacing(12)
+            .into()
+    }
+
+    // Modo de normalización de la imagen actual, con el rango que ha salido
+    fn normalization_label(&self) -> Option<String> {
+        let image = self.image.as_ref()?;
+        let mode = self.normalization.mode;
+        Some(match &image.normalizer {
+            Normalizer::Linear { min, max } => format!("Normalización: {} ({:.3} .. {:.3})", mode, min, max),
+            Normalizer::Equalized { .. } => format!("Normalización: {}", mode),
+        })
+    }
+
+    fn gradient_editor(&self) -> Element<'_, Message> {
+        let preset_list = pick_list(&GradientPreset::ALL[..], self.gradient_preset,
+                                    Message::GradientPresetSelected)
+            .placeholder("Personalizada");
+        let bands = checkbox(self.gradient.mode == GradientMode::Bands)
+            .label("Bandas discretas")
+            .on_toggle(Message::GradientBandsToggled);
+        let bar = Canvas::new(GradientBar { gradient: &self.gradient })
+            .width(250)
+            .height(20);
+
+        let can_remove = self.gradient.stops.len() > 2;
+        let stops = self.gradient.stops.iter().enumerate().map(|(index, stop)| {
+            row![
+                slider(0..=1000, (stop.position * 1000.0).round() as u32,
+                       move |val| Message::StopPositionChanged(index, val))
+                    .width(110),
+                text_input("#RRGGBB", &self.stop_inputs[index])
+                    .on_input(move |input| Message::StopColorChanged(index, input))
+                    .width(90),
+                button("x").on_press_maybe(can_remove.then_some(Message::RemoveStop(index))),
+            ]
+            .spacing(6)
+            .into()
+        });
+
+        column![
+            text("Paleta:"),
+            row![preset_list, bands].spacing(12),
+            bar,
+        ]
+        .extend(stops)
+        .push(button("Añadir parada").on_press(Message::AddStop))
+        .spacing(8)
+        .width(250)
+        .into()
+    }
+
+    // Barra de estado con lo que hay bajo el cursor
+    fn hover_readout(&self) -> Option<Element<'_, Message>> {
+        let image = self.image.as_ref()?;
+        let Some(hover) = self.hover else {
+            return Some(text("Pasa el cursor por la imagen; clic derecho fija una sonda").into());
+        };
+        let (x, y) = image.pixel_at(hover.point)?;
+        let [sx, sy] = image.sample_point(hover.point);
+        let index = (y * image.width + x) as usize;
+        let [r, g, b] = [0, 1, 2].map(|c| image.pixels[index * 4 + c]);
 
-        maxvalue += amplitude;
-        amplitude *= persistence;
-        frequency *= lacunarity;
+        // En las vistas previas el pixel se refiere a la imagen final
+        let scale_x = image.display_width as f32 / image.width as f32;
+        let scale_y = image.display_height as f32 / image.height as f32;
+        Some(
+            row![
+                text(format!("Pixel: ({}, {})", (x as f32 * scale_x) as u32, (y as f32 * scale_y) as u32)),
+                text(format!("Muestra: ({:.5}, {:.5})", sx, sy)),
+                text(format!("Valor: {:.5}", image.heightmap.get(x, y))),
+                color_swatch([r, g, b]),
+                text(palette::to_hex([r, g, b])),
+            ]
+            .spacing(16)
+            .into(),
+        )
     }
 
-    total / maxvalue // Normalizamos a -1.0..1.0 (más o menos)
-}
+    // Histograma y resumen de los valores crudos de la imagen actual
+    fn stats_panel(&self) -> Option<Element<'_, Message>> {
+        let image = self.image.as_ref()?;
+        let stats = &image.stats;
 
-fn perlin_to_color(value: f64) -> [u8; 4] {
-    let normalized = (((value + 1.0) / 2.0) * 255.999) as u8; // Normalizamos a 0..255
-    [normalized, normalized, normalized, 255] // RGBA
-}
+        let histogram = Canvas::new(HistogramChart {
+            bins: &image.histogram,
+            range: (stats.min, stats.max),
+            threshold: self.threshold,
+            gradient: &self.gradient,
+            normalizer: &image.normalizer,
+        })
+        .width(Length::Fixed(320.0))
+        .height(Length::Fixed(110.0));
 
-fn apply_perlin(params: & PaintApp) -> Vec<u8> {
-    let randnum = rand::thread_rng().gen();
-    let mut pixels = Vec::with_capacity((params.img_width.val * params.img_height.val * 4) as usize);
-    let perlin = Perlin::new(randnum);
-    let dlacunarity:  f64 = params.lacunarity.scale();
-    let dpersistence: f64 = params.persistence.scale();
-    let d_amplitude:  f64 = params.amplitude.scale();
-    let d_frequency:  f64 = params.frequency.scale();
+        let summary = column![
+            text(format!("Mínimo: {:.4}", stats.min)),
+            text(format!("Máximo: {:.4}", stats.max)),
+            text(format!("Media: {:.4}", stats.mean)),
+            text(format!("Desviación típica: {:.4}", stats.std_dev)),
+            text(format!("Por encima de {:.2}: {:.1}%", self.threshold,
+                         stats.fraction_above(self.threshold) * 100.0)),
+            container(slider(-1.0..=1.0, self.threshold, Message::ThresholdChanged).step(0.01)).width(200),
+        ]
+        .spacing(4);
 
-    for j in 0..(params.img_height.val) {
-        for i in 0..(params.img_width.val) {
-            let x = i as f64 / params.img_width.val as f64;
-            let y = j as f64 / params.img_height.val as f64;
-            let prev = fractal_noise(&perlin, [x, y], params.octaves.val, dlacunarity, dpersistence, d_frequency, d_amplitude);
-            pixels.extend_from_slice(&perlin_to_color(prev));
-        }
+        Some(row![histogram, summary].spacing(16).into())
     }
 
-    pixels
-}
+    // Color, nombre y superficie de los biomas que aparecen en la imagen
+    fn biome_legend(&self) -> Option<Element<'_, Message>> {
+        let map = self.image.as_ref()?.biome_map.as_ref()?;
+        let areas = map.areas(self.biomes.biomes.len());
 
-fn main() -> iced::Result {
-    // iced::run("Canvas con imagen", PaintApp::update, PaintApp::view)
-    iced::application(PaintApp::default, PaintApp::update, PaintApp::view)
-        .theme(Theme::CatppuccinMocha)
-        .title("Generador de Ruido Perlin")
-        .run()
-}
+        let entries = self.biomes.biomes.iter().zip(areas)
+            .filter(|(_, area)| *area > 0.0)
+            .map(|(biome, area)| {
+                row![
+                    color_swatch(biome.color),
+                    text(biome.name.as_str()).width(140),
+                    text(format!("{:.1}%", area * 100.0)),
+                ]
+                .spacing(8)
+                .into()
+            });
 
-trait Scallable {
-    fn scale(&self) -> f64;
-}
+        Some(
+            column![text("Biomas")]
+                .push(container(scrollable(Column::with_children(entries).spacing(4))).max_height(140))
+                .spacing(6)
+                .into(),
+        )
+    }
 
-#[derive(Clone)]
-struct BoundedParam {
-    val: u32,
-    min: u32,
-    max: u32,
-    step: u32,
-}
+    fn probe_list(&self) -> Option<Element<'_, Message>> {
+        let image = self.image.as_ref()?;
+        if self.probes.is_empty() {
+            return None;
+        }
 
-#[derive(Clone)]
-struct ScaledBoundedParam {
-    val: u32,
-    min: u32,
-    max: u32,
-    step: u32,
-    scale: f64,
-}
+        let rows = self.probes.iter().enumerate().map(|(index, &pos)| {
+            let value = image.value_at(pos);
+            let color = self.gradient.color_at(image.normalizer.apply(value));
+            row![
+                text(format!("#{}", index + 1)).width(30),
+                text(format!("({:.5}, {:.5})", pos[0], pos[1])).width(200),
+                text(format!("{:.5}", value)).width(90),
+                color_swatch(color),
+                button("x").on_press(Message::RemoveProbe(index)),
+            ]
+            .spacing(8)
+            .into()
+        });
 
-impl Scallable for ScaledBoundedParam {
-    fn scale(&self) -> f64 { self.val as f64 / self.scale }
-}
+        Some(
+            column![
+                row![
+                    text("Sondas"),
+                    button("Quitar todas").on_press(Message::ClearProbes),
+                ]
+                .spacing(12),
+                container(scrollable(Column::with_children(rows).spacing(4))).max_height(140),
+            ]
+            .spacing(6)
+            .into(),
+        )
+    }
 
-#[derive(Clone)]
-struct PaintApp {
-    image: Option<(u32, u32, Handle)>, // ancho, alto, pixels RGBA
-    octaves: BoundedParam,
-    lacunarity: ScaledBoundedParam,
-    persistence: ScaledBoundedParam,
-    frequency: ScaledBoundedParam,
-    amplitude: ScaledBoundedParam,
-    img_width: BoundedParam,
-    img_height: BoundedParam,
-}
+    // Añadir nodos, evaluar y editar el nodo seleccionado
+    fn graph_controls(&self) -> Element<'_, Message> {
+        let evaluating = self.job.as_ref().is_some_and(|job| job.graph.is_some());
+        let toolbar = row![
+            pick_list(NodeKind::templates(), None::<NodeKind>, Message::AddGraphNode).placeholder("Añadir nodo"),
+            button(if evaluating { "Evaluando..." } else { "Evaluar grafo" })
+                .on_press_maybe((!evaluating).then_some(Message::EvaluateGraph)),
+            text("Arrastra de una salida a una entrada para conectar; desde una entrada, para soltar el cable"),
+        ]
+        .spacing(12)
+        .align_y(iced::Alignment::Center);
 
-impl Default for PaintApp {
-    fn default() -> Self {
-        PaintApp {
-            image: None,
-            octaves: BoundedParam { val: 8, min: 1, max: 20, step: 1 },
-            lacunarity: ScaledBoundedParam { val: 20, min: 1, max: 40, step: 1, scale: 10.0 },
-            persistence: ScaledBoundedParam { val: 50, min: 1, max: 100, step: 1, scale: 100.0 },
-            frequency: ScaledBoundedParam { val: 50, min: 1, max: 10000, step: 1, scale: 100.0 },
-            amplitude: ScaledBoundedParam { val: 50, min: 1, max: 1000, step: 1, scale: 100.0 },
-            img_width: BoundedParam { val: 1000, min: 50, max: 2000, step: 100 },
-            img_height: BoundedParam { val: 600, min: 50, max: 2000, step: 100 },
-        }
+        let status = self.graph_status.as_ref().map(|status| match status {
+            Ok(message) => text(message.clone()),
+            Err(error) => text(format!("Error en el grafo: {}", error)),
+        });
+
+        column![toolbar]
+            .push(status)
+            .push(self.selected_node.and_then(|id| self.graph.node(id)).map(|node| self.node_inspector(node.id, &node.kind)))
+            .spacing(8)
+            .into()
     }
-}
 
-#[derive(Debug, Clone)]
-enum Message {
-    Clear,
-    ApplyTestImage,
-    OctavesChanged(u32),
-    LacunarityChanged(u32),
-    PersistenceChanged(u32),
-    DAmplitudeChanged(u32),
-    DFrequencyChanged(u32),
-    ImgWidthChanged(u32),
-    ImgHeightChanged(u32),
-}
+    fn node_inspector<'a>(&self, id: NodeId, kind: &'a NodeKind) -> Element<'a, Message> {
+        let changed = move |kind: NodeKind| Message::GraphNodeChanged(id, kind);
+        let labeled = |label: String, control: Element<'a, Message>| -> Element<'a, Message> {
+            row![text(label).width(130), container(control).width(200)]
+                .spacing(8)
+                .align_y(iced::Alignment::Center)
+                .into()
+        };
 
-impl PaintApp {
-    fn update(&mut self, message: Message) {
-        match message {
-            Message::Clear => {
-              self.image = None;
-            },
-            Message::ApplyTestImage => {
-                let pixels = apply_perlin(self);
-                let handle = Handle::from_rgba(self.img_width.val, self.img_height.val, pixels);
-                self.image = Some((self.img_width.val, self.img_height.val, handle));
-            },
-            Message::OctavesChanged(val) => self.octaves.val = val,
-            Message::LacunarityChanged(val) => self.lacunarity.val = val,
-            Message::PersistenceChanged(val) => self.persistence.val = val,
-            Message::DAmplitudeChanged(val) => self.amplitude.val = val,
-            Message::DFrequencyChanged(val) => self.frequency.val = val,
-            Message::ImgWidthChanged(val) => self.img_width.val = val,
-            Message::ImgHeightChanged(val) => self.img_height.val = val,
-        }
+        let fields: Vec<Element<'a, Message>> = match kind {
+            NodeKind::Noise { params } => vec![
+                labeled(String::from("Ruido"), pick_list(&NoiseKind::ALL[..], Some(params.noise),
+                    noise_field(id, params, |params, noise| params.noise = noise)).into()),
+                labeled(String::from("Fractal"), pick_list(&FractalKind::ALL[..], Some(params.fractal),
+                    noise_field(id, params, |params, fractal| params.fractal = fractal)).into()),
+                labeled(format!("Octavas: {}", params.octaves), slider(1..=12, params.octaves,
+                    noise_field(id, params, |params, octaves| params.octaves = octaves)).into()),
+                labeled(format!("Frecuencia: {:.2}", params.frequency), slider(0.1..=20.0, params.frequency,
+                    noise_field(id, params, |params, frequency| params.frequency = frequency)).step(0.05).into()),
+                labeled(format!("Persistencia: {:.2}", params.persistence), slider(0.01..=1.0, params.persistence,
+                    noise_field(id, params, |params, persistence| params.persistence = persistence)).step(0.01).into()),
+                row![
+                    button("Semilla aleatoria")
+                        .on_press(Message::RandomizeNodeSeed(id)),
+                    button("Copiar de los controles")
+                        .on_press(changed(NodeKind::Noise { params: NoiseParams { layers: Vec::new(), ..self.layer_params() } })),
+                ]
+                .spacing(8)
+                .into(),
+            ],
+            &NodeKind::Constant { value } => vec![
+                labeled(format!("Valor: {:.2}", value), slider(-1.0..=1.0, value, move |value| {
+                    changed(NodeKind::Constant { value })
+                }).step(0.01).into()),
+            ],
+            &NodeKind::Gradient { angle } => vec![
+                labeled(format!("Ángulo: {:.0}°", angle), slider(0.0..=360.0, angle, move |angle| {
+                    changed(NodeKind::Gradient { angle })
+                }).into()),
+            ],
+            &NodeKind::Mix { factor } => vec![
+                labeled(format!("Mezcla: {:.2}", factor), slider(0.0..=1.0, factor, move |factor| {
+                    changed(NodeKind::Mix { factor })
+                }).step(0.01).into()),
+            ],
+            &NodeKind::Clamp { min, max } => vec![
+                labeled(format!("Mínimo: {:.2}", min), slider(-1.0..=1.0, min, move |min| {
+                    changed(NodeKind::Clamp { min, max })
+                }).step(0.01).into()),
+                labeled(format!("Máximo: {:.2}", max), slider(-1.0..=1.0, max, move |max| {
+                    changed(NodeKind::Clamp { min, max })
+                }).step(0.01).into()),
+            ],
+            &NodeKind::Curve { exponent } => vec![
+                labeled(format!("Exponente: {:.2}", exponent), slider(0.1..=5.0, exponent, move |exponent| {
+                    changed(NodeKind::Curve { exponent })
+                }).step(0.05).into()),
+            ],
+            &NodeKind::Blur { radius } => vec![
+                labeled(format!("Radio: {}", radius), slider(0..=20, radius, move |radius| {
+                    changed(NodeKind::Blur { radius })
+                }).into()),
+            ],
+            &NodeKind::Erode { radius } => vec![
+                labeled(format!("Radio: {}", radius), slider(0..=20, radius, move |radius| {
+                    changed(NodeKind::Erode { radius })
+                }).into()),
+            ],
+            NodeKind::Export { path } => vec![
+                labeled(String::from("Fichero"), text_input("grafo.png", path)
+                    .on_input(move |path| changed(NodeKind::Export { path }))
+                    .into()),
+            ],
+            NodeKind::Add | NodeKind::Image => vec![text("Sin parámetros").into()],
+        };
+
+        column![
+            row![
+                text(format!("Nodo {}: {}", id, kind)),
+                button("Borrar nodo").on_press(Message::RemoveGraphNode(id)),
+            ]
+            .spacing(12)
+            .align_y(iced::Alignment::Center),
+        ]
+        .extend(fields)
+        .spacing(6)
+        .into()
     }
 
-    fn view(&self) -> Element<Message> {
-        use iced::widget::scrollable;
+    fn view(&self) -> Element<'_, Message> {
+        let canvas: Element<'_, Message> = if self.graph_mode {
+            Canvas::new(GraphEditor { graph: &self.graph, selected: self.selected_node })
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into()
+        } else {
+            Canvas::new(self)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .into()
+        };
+
+
+        let noise_kind_text = text("Tipo de ruido:");
+        let noise_kind_list = container(
+            pick_list(&NoiseKind::ALL[..], Some(self.noise_kind), Message::NoiseKindChanged),
+        )
+        .width(250);
 
-        let canvas = Canvas::new(self)
+        let fractal_kind_text = text("Tipo de fractal:");
+        let fractal_kind_list = container(
+            pick_list(&FractalKind::ALL[..], Some(self.fractal_kind), Message::FractalKindChanged),
+        )
+        .width(250);
 
-        .width(Length::Fixed(self.img_width.val as f32))
-        .height(Length::Fixed(self.img_height.val as f32));
+        // Sólo se muestran los parámetros que usa el fractal elegido
+        let offset_controls = self.fractal_kind.uses_offset().then(|| column![
+            text(format!("Offset: {}", self.offset.scale())),
+            container(
+                slider(self.offset.min ..= self.offset.max, self.offset.val, Message::OffsetChanged)
+                    .shift_step(self.offset.step),
+            )
+            .width(250),
+        ].spacing(12));
+        let gain_controls = self.fractal_kind.uses_gain().then(|| column![
+            text(format!("Ganancia: {}", self.gain.scale())),
+            container(
+                slider(self.gain.min ..= self.gain.max, self.gain.val, Message::GainChanged)
+                    .shift_step(self.gain.step),
+            )
+            .width(250),
+        ].spacing(12));
+
+        let fractal_controls = column![fractal_kind_text, fractal_kind_list]
+            .push(offset_controls)
+            .push(gain_controls)
+            .spacing(12);
+
+        let seed_text = text("Semilla:");
+        let seed_controls = row![
+            text_input("0", &self.seed_input)
+                .on_input(Message::SeedInputChanged)
+                .width(120),
+            button("Aleatoria").on_press(Message::RandomizeSeed),
+        ]
+        .spacing(8)
+        .width(250);
+        let seed_lock = checkbox(self.seed_locked)
+            .label("Bloquear semilla")
+            .on_toggle(Message::SeedLockToggled);
 
-        let scrollable_canvas = scrollable(canvas)
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .direction(Direction::Both { vertical: Scrollbar::new(), horizontal: Scrollbar::new() });
-            
         let octaves_slider = container(
             slider(self.octaves.min ..= self.octaves.max, self.octaves.val, Message::OctavesChanged)
                 // .default(8u32)
@@ -223,6 +2362,26 @@ impl PaintApp {
         let controls = column![
             button("Limpiar").on_press(Message::Clear),
             button("Aplicar imagen de prueba").on_press(Message::ApplyTestImage),
+            checkbox(self.auto_regenerate)
+                .label("Regenerar al mover los controles")
+                .on_toggle(Message::AutoRegenerateToggled),
+            self.history_controls(),
+            checkbox(self.keep_thumbnails)
+                .label("Guardar miniaturas")
+                .on_toggle(Message::ThumbnailsToggled),
+            rule::horizontal(1),
+            self.preset_browser(),
+            rule::horizontal(1),
+            self.layer_panel(),
+            rule::horizontal(1),
+            noise_kind_text, noise_kind_list,
+            checkbox(self.tileable)
+                .label("Repetible sin costuras")
+                .on_toggle(Message::TileableToggled),
+            fractal_controls,
+            rule::horizontal(1),
+            seed_text, seed_controls, seed_lock,
+            rule::horizontal(1),
             octaves_slider_text, octaves_slider,
             rule::horizontal(1),
             lacunarity_slider_text, lacunarity_slider,
@@ -233,17 +2392,115 @@ impl PaintApp {
             rule::horizontal(1),
             d_frequency_slider_text, d_frequency_slider,
             rule::horizontal(1),
+            self.warp_editor(),
+            rule::horizontal(1),
             img_width_slider_text, img_width_slider,
             rule::horizontal(1),
             img_height_slider_text, img_height_slider,
+            rule::horizontal(1),
+            self.post_process_editor(),
+            rule::horizontal(1),
+            self.river_editor(),
+            rule::horizontal(1),
+            self.biome_editor(),
+            rule::horizontal(1),
+            self.normalization_editor(),
+            rule::horizontal(1),
+            self.gradient_editor(),
         ]
         .padding(12)
         .spacing(12)
         .width(Length::Shrink);
 
+        let controls = scrollable(controls).height(Length::Fill);
+
+        let seed_label = text(match &self.image {
+            Some(image) => format!("Semilla: {}", image.preset.params.seed),
+            None => String::from("Sin imagen"),
+        });
+
+        let export_controls = row![
+            text_input("ruido.png", &self.export_path)
+                .on_input(Message::ExportPathChanged)
+                .width(250),
+            if self.exporting {
+                button("Exportando...")
+            } else {
+                // La vista previa no tiene la resolución final
+                button("Exportar PNG").on_press_maybe(self.image.as_ref().filter(|image| !image.preview).map(|_| Message::Export))
+            },
+        ]
+        .spacing(8);
+
+        let export_status = self.export_result.as_ref().map(|result| match result {
+            Ok(path) => text(format!("Imagen guardada en {}", path)),
+            Err(PngError(error)) => text(format!("No se pudo guardar la imagen: {}", error)),
+        });
+
+        let generation_error = self.generation_error.as_ref()
+            .map(|error| text(format!("No se pudo generar la imagen: {}", error)));
+
+        let job_status = self.job.as_ref().map(|job| {
+            let label = match (&job.graph, job.preview) {
+                (Some(_), _) => "Evaluando el grafo...",
+                (None, true) => "Vista previa...",
+                (None, false) => "Generando...",
+            };
+            row![
+                text(label),
+                progress_bar(0.0..=1.0, job.progress).length(300),
+                button("Cancelar").on_press(Message::CancelGeneration),
+            ]
+            .spacing(12)
+        });
+
+        let zoom = match (&self.image, self.view) {
+            (Some(_), Some(view)) => format!("Zoom: {:.0}%", view.scale * 100.0),
+            (Some(_), None) => String::from("Zoom: ajustado"),
+            (None, _) => String::new(),
+        };
+        let view_controls = row![
+            button("Ajustar a la ventana").on_press(Message::FitToWindow),
+            button("Re-muestrear zona visible")
+                .on_press_maybe(self.visible_region().map(|_| Message::ResampleView)),
+            button("Restablecer zona")
+                .on_press_maybe((self.region != Region::default()).then_some(Message::ResetRegion)),
+            checkbox(self.tile_preview)
+                .label("Mosaico 2x2")
+                .on_toggle(Message::TilePreviewToggled),
+            checkbox(self.graph_mode)
+                .label("Editor de nodos")
+                .on_toggle(Message::GraphModeToggled),
+            text(zoom),
+        ]
+        .spacing(8);
+
+        let compare = self.image.as_ref().filter(|image| image.before.is_some()).map(|_| {
+            row![
+                text("Sin postproceso a la izquierda de:"),
+                slider(0.0..=1.0, self.compare, Message::CompareChanged).step(0.01).width(200),
+                text(format!("{:.0}%", self.compare * 100.0)),
+            ]
+            .spacing(8)
+        });
+
         let viewer = column![
-            scrollable_canvas,
+            row![seed_label, export_controls]
+                .push(self.normalization_label().map(text))
+                .spacing(24),
+            view_controls,
         ]
+        .push(self.thumbnail_strip())
+        .push(self.graph_mode.then(|| self.graph_controls()))
+        .push(compare.filter(|_| !self.graph_mode))
+        .push(job_status)
+        .push(generation_error)
+        .push(export_status)
+        .push(canvas)
+        .push(self.hover_readout())
+        .push(self.probe_list())
+        .push(self.stats_panel())
+        .push(self.biome_legend())
         .padding(12)
         .spacing(12);
 
@@ -262,16 +2519,58 @@ impl PaintApp {
 }
 
 impl Program<Message> for PaintApp {
-    type State = ();
+    type State = CanvasState;
 
     fn update(
         &self,
-        _state: &mut Self::State,
-        _event: &Event,
-        _bounds: Rectangle,
-        _cursor: mouse::Cursor,
-    ) -> Option<iced::widget::Action<Message>> {
-        None
+        state: &mut Self::State,
+        event: &Event,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> Option<Action<Message>> {
+        let image = self.image.as_ref()?;
+        let view = self.effective_view(bounds.size(), image);
+
+        match event {
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+                let position = cursor.position_in(bounds)?;
+                let lines = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } => *y,
+                    mouse::ScrollDelta::Pixels { y, .. } => *y / 50.0,
+                };
+                let view = view.zoom_at(position, 1.2f32.powf(lines));
+                Some(Action::publish(Message::ViewChanged(view, bounds.size())).and_capture())
+            },
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                state.drag = Some(cursor.position_in(bounds)?);
+                Some(Action::capture())
+            },
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+                let point = self.tile_point(image, view.to_image(cursor.position_in(bounds)?));
+                image.pixel_at(point)?;
+                Some(Action::publish(Message::PinProbe(point)).and_capture())
+            },
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+                if let Some(from) = state.drag {
+                    // Seguimos arrastrando aunque el cursor salga del lienzo
+                    let to = cursor.position_from(bounds.position())?;
+                    state.drag = Some(to);
+                    let view = ViewTransform { offset: view.offset + (to - from), ..view };
+                    return Some(Action::publish(Message::ViewChanged(view, bounds.size())).and_capture());
+                }
+
+                let hover = cursor.position_in(bounds)
+                    .map(|position| self.tile_point(image, view.to_image(position)))
+                    .filter(|&point| image.pixel_at(point).is_some())
+                    .map(|point| Hover { point });
+                (hover != self.hover).then(|| Action::publish(Message::Hovered(hover)))
+            },
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                state.drag.take()?;
+                Some(Action::capture())
+            },
+            _ => None,
+        }
     }
 
     fn draw(
@@ -284,37 +2583,396 @@ impl Program<Message> for PaintApp {
     ) -> Vec<Geometry> {
         let mut frame = Frame::new(renderer, bounds.size());
         // Dibujar imagen si existe
-        if let Some((width, height, handle)) = &self.image {
-            self.draw_image_from_rgba(&mut frame, *width, *height, handle);
+        if let Some(image) = &self.image {
+            let view = self.effective_view(bounds.size(), image);
+            self.draw_image_from_rgba(&mut frame, image, view);
+            if self.river_overlay {
+                self.draw_rivers(&mut frame, image, view);
+            }
+            self.draw_probes(&mut frame, image, view);
         }
 
         vec![frame.into_geometry()]
     }
+
+    fn mouse_interaction(
+        &self,
+        state: &Self::State,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> mouse::Interaction {
+        if state.drag.is_some() {
+            mouse::Interaction::Grabbing
+        } else if self.image.is_some() && cursor.is_over(bounds) {
+            mouse::Interaction::Grab
+        } else {
+            mouse::Interaction::default()
+        }
+    }
 }
 
 impl PaintApp {
     fn draw_image_from_rgba(
         &self,
         frame: &mut Frame,
-        width: u32,
-        height: u32,
-        handle: &Handle,
+        image: &GeneratedImage,
+        view: ViewTransform,
     ) {
-        // Crear CanvasImage
-        let canvas_img = CanvasImage::new(handle.clone());
-
-        let bounds = Rectangle {
-            x: 0.0,
-            y: 0.0,
-            width: width as f32,
-            height: height as f32,
+        // Al ampliar se ven los pixels tal cual, sin suavizar
+        let filter = if view.scale > 1.0 { FilterMethod::Nearest } else { FilterMethod::Linear };
+        let width = image.display_width as f32 * view.scale;
+        let height = image.display_height as f32 * view.scale;
+
+        // Dibujar imagen, una vez por copia del mosaico
+        let draw_tiles = |frame: &mut Frame, handle: &Handle| {
+            for row in 0..self.tiles() {
+                for col in 0..self.tiles() {
+                    let bounds = Rectangle {
+                        x: view.offset.x + col as f32 * width,
+                        y: view.offset.y + row as f32 * height,
+                        width,
+                        height,
+                    };
+                    frame.draw_image(bounds, CanvasImage::new(handle.clone()).filter_method(filter));
+                }
+            }
         };
+        draw_tiles(frame, &image.handle);
+
+        // A la izquierda de la división se ve el campo antes del postproceso
+        if let Some((_, before)) = image.before.as_ref().filter(|_| self.compare > 0.0) {
+            let split = view.offset.x + width * self.tiles() as f32 * self.compare;
+            let clip = Rectangle::new(Point::ORIGIN, Size::new(split.max(0.0), frame.height()));
+            frame.with_clip(clip, |frame| draw_tiles(frame, before));
+            let line = Path::line(Point::new(split, 0.0), Point::new(split, frame.height()));
+            frame.stroke(&line, Stroke::default().with_color(Color::WHITE).with_width(1.5));
+        }
+    }
+
+    // Los ríos sobre la primera copia del mosaico, como las sondas
+    fn draw_rivers(&self, frame: &mut Frame, image: &GeneratedImage, view: ViewTransform) {
+        let Some(network) = &image.rivers else { return };
+        // De pixels del heightmap a pantalla
+        let scale = image.display_width as f32 / image.width as f32 * view.scale;
+        let screen = |[x, y]: [f64; 2]| Point::new(view.offset.x + x as f32 * scale, view.offset.y + y as f32 * scale);
+
+        for river in &network.rivers {
+            let curves = river.curves();
+            let Some(first) = curves.first() else { continue };
+            let path = Path::new(|p| {
+                p.move_to(screen(first.from));
+                for curve in &curves {
+                    p.quadratic_curve_to(screen(curve.control), screen(curve.to));
+                }
+            });
+            let width = (network.stroke_width(river) as f32 * scale).max(1.0);
+            frame.stroke(&path, Stroke::default().with_color(Color::from_rgb8(48, 112, 208)).with_width(width));
+        }
+    }
+
+    fn draw_probes(&self, frame: &mut Frame, image: &GeneratedImage, view: ViewTransform) {
+        for (index, &pos) in self.probes.iter().enumerate() {
+            let point = image.display_point(pos);
+            let center = Point::new(view.offset.x + point.x * view.scale, view.offset.y + point.y * view.scale);
+
+            // Doble trazo para que se vea sobre cualquier color
+            let marker = Path::circle(center, 5.0);
+            frame.stroke(&marker, Stroke::default().with_color(Color::BLACK).with_width(3.0));
+            frame.stroke(&marker, Stroke::default().with_color(Color::WHITE).with_width(1.5));
+            frame.fill_text(canvas::Text {
+                content: format!("{}", index + 1),
+                position: center + Vector::new(7.0, -16.0),
+                color: Color::WHITE,
+                ..canvas::Text::default()
+            });
+        }
+    }
+}
+
+// Vista previa del degradado de la paleta
+struct GradientBar<'a> {
+    gradient: &'a Gradient,
+}
+
+impl Program<Message> for GradientBar<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &iced::Renderer,
+        _theme: &iced::Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let columns = bounds.width.max(1.0) as usize;
+        let lut = self.gradient.lut(columns);
+
+        for (x, [r, g, b]) in lut.into_iter().enumerate() {
+            frame.fill_rectangle(
+                Point::new(x as f32, 0.0),
+                Size::new(1.0, bounds.height),
+                Color::from_rgb8(r, g, b),
+            );
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
+
+// Histograma de los valores crudos, cada barra con el color que le toca
+struct HistogramChart<'a> {
+    bins: &'a [u32],
+    range: (f64, f64),
+    threshold: f64,
+    gradient: &'a Gradient,
+    normalizer: &'a Normalizer,
+}
+
+impl Program<Message> for HistogramChart<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &iced::Renderer,
+        _theme: &iced::Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let peak = self.bins.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let bar_width = bounds.width / self.bins.len().max(1) as f32;
+        let (min, max) = self.range;
+        let span = max - min;
+
+        for (i, &count) in self.bins.iter().enumerate() {
+            let center = min + span * (i as f64 + 0.5) / self.bins.len() as f64;
+            let [r, g, b] = self.gradient.color_at(self.normalizer.apply(center));
+            let height = count as f32 / peak * bounds.height;
+            frame.fill_rectangle(
+                Point::new(i as f32 * bar_width, bounds.height - height),
+                Size::new(bar_width.max(1.0), height),
+                Color::from_rgb8(r, g, b),
+            );
+        }
+
+        if span > 0.0 && (min..=max).contains(&self.threshold) {
+            let x = ((self.threshold - min) / span) as f32 * bounds.width;
+            let line = Path::line(Point::new(x, 0.0), Point::new(x, bounds.height));
+            frame.stroke(&line, Stroke::default().with_color(Color::from_rgb8(230, 60, 60)).with_width(2.0));
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
+
+// Medidas de los nodos en el editor
+const NODE_WIDTH: f32 = 150.0;
+const NODE_HEADER: f32 = 24.0;
+const NODE_ROW: f32 = 22.0;
+const PORT_RADIUS: f32 = 6.0;
+
+fn node_size(kind: &NodeKind) -> Size {
+    Size::new(NODE_WIDTH, NODE_HEADER + kind.inputs().len().max(1) as f32 * NODE_ROW + 6.0)
+}
+
+fn input_port(node: &Node, input: usize) -> Point {
+    Point::new(node.position[0], node.position[1] + NODE_HEADER + (input as f32 + 0.5) * NODE_ROW)
+}
+
+fn output_port(node: &Node) -> Point {
+    Point::new(node.position[0] + NODE_WIDTH, node.position[1] + NODE_HEADER + 0.5 * NODE_ROW)
+}
+
+// Cable de una salida a una entrada: sale y entra en horizontal
+fn wire_path(from: Point, to: Point) -> Path {
+    let bend = ((to.x - from.x).abs() / 2.0).max(50.0);
+    Path::new(|p| {
+        p.move_to(from);
+        p.bezier_curve_to(from + Vector::new(bend, 0.0), to - Vector::new(bend, 0.0), to);
+    })
+}
+
+// Editor del grafo de nodos; como el lienzo de curvas de mainBezier, pero con
+// nodos que se arrastran y cables entre sus puertos
+struct GraphEditor<'a> {
+    graph: &'a Graph,
+    selected: Option<NodeId>,
+}
+
+#[derive(Default)]
+struct GraphEditorState {
+    drag: Option<GraphDrag>,
+}
+
+enum GraphDrag {
+    Node { id: NodeId, grab: Vector }, // del cursor a la esquina del nodo
+    Wire { from: NodeId, to: Point },  // cable suelto siguiendo al cursor
+}
+
+impl GraphEditor<'_> {
+    // El de más arriba, que es el último que se pinta
+    fn node_at(&self, point: Point) -> Option<&Node> {
+        self.graph.nodes.iter().rev().find(|node| {
+            Rectangle::new(Point::new(node.position[0], node.position[1]), node_size(&node.kind)).contains(point)
+        })
+    }
+
+    fn output_at(&self, point: Point) -> Option<NodeId> {
+        self.graph.nodes.iter()
+            .filter(|node| node.kind.has_output())
+            .find(|node| output_port(node).distance(point) <= PORT_RADIUS * 1.5)
+            .map(|node| node.id)
+    }
+
+    fn input_at(&self, point: Point) -> Option<(NodeId, usize)> {
+        self.graph.nodes.iter().find_map(|node| {
+            (0..node.kind.inputs().len())
+                .find(|&input| input_port(node, input).distance(point) <= PORT_RADIUS * 1.5)
+                .map(|input| (node.id, input))
+        })
+    }
+}
 
-        // Dibujar imagen
-        frame.draw_image(bounds, canvas_img);
+impl Program<Message> for GraphEditor<'_> {
+    type State = GraphEditorState;
+
+    fn update(
+        &self,
+        state: &mut Self::State,
+        event: &Event,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> Option<Action<Message>> {
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let point = cursor.position_in(bounds)?;
+                if let Some(from) = self.output_at(point) {
+                    state.drag = Some(GraphDrag::Wire { from, to: point });
+                    return Some(Action::capture());
+                }
+                // Tirar de una entrada conectada suelta el cable para llevarlo a otra
+                if let Some((to, input)) = self.input_at(point) {
+                    let from = self.graph.input(to, input)?;
+                    state.drag = Some(GraphDrag::Wire { from, to: point });
+                    return Some(Action::publish(Message::DisconnectGraph(to, input)).and_capture());
+                }
+                match self.node_at(point) {
+                    Some(node) => {
+                        let grab = point - Point::new(node.position[0], node.position[1]);
+                        state.drag = Some(GraphDrag::Node { id: node.id, grab });
+                        Some(Action::publish(Message::SelectGraphNode(Some(node.id))).and_capture())
+                    },
+                    None => self.selected.map(|_| Action::publish(Message::SelectGraphNode(None))),
+                }
+            },
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+                let point = cursor.position_from(bounds.position())?;
+                match state.drag.as_mut()? {
+                    GraphDrag::Node { id, grab } => {
+                        Some(Action::publish(Message::MoveGraphNode(*id, point - *grab)).and_capture())
+                    },
+                    GraphDrag::Wire { to, .. } => {
+                        *to = point;
+                        Some(Action::request_redraw().and_capture())
+                    },
+                }
+            },
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                match state.drag.take()? {
+                    GraphDrag::Wire { from, to } => match self.input_at(to) {
+                        Some((to, input)) => Some(Action::publish(Message::ConnectGraph(Wire { from, to, input })).and_capture()),
+                        None => Some(Action::request_redraw().and_capture()),
+                    },
+                    GraphDrag::Node { .. } => Some(Action::capture()),
+                }
+            },
+            _ => None,
+        }
+    }
+
+    fn draw(
+        &self,
+        state: &Self::State,
+        renderer: &iced::Renderer,
+        _theme: &iced::Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        frame.fill_rectangle(Point::ORIGIN, bounds.size(), Color::from_rgb8(24, 24, 37));
+        let wire_stroke = Stroke::default().with_color(Color::from_rgb8(205, 214, 244)).with_width(2.0);
+
+        for wire in &self.graph.wires {
+            if let (Some(from), Some(to)) = (self.graph.node(wire.from), self.graph.node(wire.to)) {
+                frame.stroke(&wire_path(output_port(from), input_port(to, wire.input)), wire_stroke);
+            }
+        }
+        if let Some(GraphDrag::Wire { from, to }) = &state.drag {
+            if let Some(from) = self.graph.node(*from) {
+                frame.stroke(&wire_path(output_port(from), *to), wire_stroke.with_color(Color::from_rgb8(249, 226, 175)));
+            }
+        }
+
+        for node in &self.graph.nodes {
+            let corner = Point::new(node.position[0], node.position[1]);
+            let size = node_size(&node.kind);
+            // Cada familia de nodos con su color
+            let header = match node.kind {
+                NodeKind::Noise { .. } | NodeKind::Constant { .. } | NodeKind::Gradient { .. } => Color::from_rgb8(64, 128, 90),
+                NodeKind::Add | NodeKind::Mix { .. } | NodeKind::Clamp { .. } | NodeKind::Curve { .. } => Color::from_rgb8(62, 96, 160),
+                NodeKind::Blur { .. } | NodeKind::Erode { .. } => Color::from_rgb8(170, 110, 50),
+                NodeKind::Image | NodeKind::Export { .. } => Color::from_rgb8(160, 60, 80),
+            };
+
+            frame.fill_rectangle(corner, size, Color::from_rgb8(49, 50, 68));
+            frame.fill_rectangle(corner, Size::new(size.width, NODE_HEADER), header);
+            if self.selected == Some(node.id) {
+                frame.stroke(&Path::rectangle(corner, size), Stroke::default().with_color(Color::WHITE).with_width(2.0));
+            }
+            frame.fill_text(canvas::Text {
+                content: node.kind.to_string(),
+                position: corner + Vector::new(8.0, 4.0),
+                color: Color::WHITE,
+                ..canvas::Text::default()
+            });
+
+            for (input, name) in node.kind.inputs().iter().enumerate() {
+                let port = input_port(node, input);
+                frame.fill(&Path::circle(port, PORT_RADIUS), Color::from_rgb8(166, 173, 200));
+                frame.fill_text(canvas::Text {
+                    content: name.to_string(),
+                    position: port + Vector::new(PORT_RADIUS + 4.0, -8.0),
+                    color: Color::from_rgb8(205, 214, 244),
+                    ..canvas::Text::default()
+                });
+            }
+            if node.kind.has_output() {
+                frame.fill(&Path::circle(output_port(node), PORT_RADIUS), Color::from_rgb8(249, 226, 175));
+            }
+        }
+
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(
+        &self,
+        state: &Self::State,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> mouse::Interaction {
+        let Some(point) = cursor.position_in(bounds) else { return mouse::Interaction::default() };
+        match state.drag {
+            Some(GraphDrag::Node { .. }) => mouse::Interaction::Grabbing,
+            Some(GraphDrag::Wire { .. }) => mouse::Interaction::Crosshair,
+            None if self.output_at(point).is_some() || self.input_at(point).is_some() => mouse::Interaction::Crosshair,
+            None if self.node_at(point).is_some() => mouse::Interaction::Grab,
+            None => mouse::Interaction::default(),
+        }
     }
 }
 
-// TODO Separar PaintApp y parámetros de generación de ruido.
 // TODO Algo que indique que está pensado.
 // TODO Adaptar al nuevo iced.
\ No newline at end of file
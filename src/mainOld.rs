@@ -1,5 +1,8 @@
 use minifb::{Key, Window, WindowOptions};
 use noise::{NoiseFn, Perlin};
+use rayon::prelude::*;
+use ruprogen::normalize::Normalization;
+use ruprogen::{generate, NoiseParams};
 
 // const WIDTH: usize = 10;
 // const HEIGHT: usize = 10;
@@ -10,7 +13,9 @@ fn to_argb(val: u32) -> u32 {
     (alpha << 24) | rgb
 }
 
-fn matrix2buffer_color( matrix: &Vec<Vec<u32>>, colors: &Vec<u32>, xscale: usize, yscale: usize ) -> Vec<u32> {
+// Sólo la usa la llamada comentada en main
+#[allow(dead_code)]
+fn matrix2buffer_color( matrix: &[Vec<u32>], colors: &[u32], xscale: usize, yscale: usize ) -> Vec<u32> {
     let width: usize = matrix[0].len();
     let height: usize = matrix.len();
     let window_width = width * xscale;
@@ -33,7 +38,7 @@ fn matrix2buffer_color( matrix: &Vec<Vec<u32>>, colors: &Vec<u32>, xscale: usize
     buffer
 }
 
-fn matrix2buffer_simple( matrix: &Vec<Vec<u32>>) -> Vec<u32> {
+fn matrix2buffer_simple( matrix: &[Vec<u32>]) -> Vec<u32> {
     let width: usize = matrix[0].len();
     let height: usize = matrix.len();
     let window_width = width;
@@ -53,32 +58,10 @@ fn print_typeof<T>(_: &T) {
     println!("{}", std::any::type_name::<T>());
 }
 
-fn fractal_noise(perlin: &Perlin, pos: [f64; 2], octaves: u32, lacunarity: f64, persistence: f64) -> f64 {
-    let mut total = 0.0;
-    let mut frequency = 1.0;
-    let mut amplitude = 1.0;
-    let mut maxvalue = 0.0;
-
-    for _ in 0..octaves {
-        total += perlin.get([pos[0] * frequency, pos[1] * frequency]) * amplitude;
-
-        maxvalue += amplitude;
-        amplitude *= persistence;
-        frequency *= lacunarity;
-    }
-
-    total / maxvalue // Normalizamos a -1.0..1.0 (más o menos)
-}
-
 fn main() {
 
-    let xscale : usize = 1;
-    let yscale : usize = 1;
-
     let width: usize = 1000;// matrix[0].len();
     let height: usize = 1000;// matrix.len();
-    // let window_width = width * xscale;
-    // let window_height = height * yscale;
 
     let perlin = Perlin::new(0);
     
@@ -96,30 +79,31 @@ fn main() {
     //     vec![1, 2, 3, 0, 1, 2, 3, 0, 1, 2],
     // ];
     
-    // TODO solo se ve gris. Hay que ver la salida de  perlin.get
     println!("perlin: {:>3}", perlin.get([0.0,0.0]));
     println!("perlin: {:>3}", perlin.get([0.1,0.0]));
     println!("perlin: {:>3}", perlin.get([0.0,0.1]));
     println!("perlin: {:>3}", perlin.get([7.0,2.0]));
     
     // Generar la matriz
-    let matrix: Vec<Vec<u32>> = (0..height)
-        .map(|y| {
-            (0..width)
-                .map(|x| {
-                    // Coordenadas escaladas
-                    let nx = (x * xscale) as f64 / (width as f64);
-                    let ny = (y * yscale) as f64 / (height as f64);
-
-                    // Obtener valor Perlin en [-1.0, 1.0]
-                    // let value = perlin.get([nx, ny]);
-
-                    let value = fractal_noise(&perlin, [nx, ny], 8, 4.0, 0.8);
-
-                    // Escalar a rango 0-255 y convertir a u32
-                    let mapped = ((value + 1.0) * 0.5 * 255.0).round() as u32;
-                    mapped
-                })
+    let heightmap = generate(&NoiseParams {
+        octaves: 8,
+        lacunarity: 4.0,
+        persistence: 0.8,
+        frequency: 1.0,
+        amplitude: 1.0,
+        seed: 0,
+        width: width as u32,
+        height: height as u32,
+        ..NoiseParams::default()
+    });
+    // El fractal casi nunca llega a -1..1: estirar al rango real para que no salga gris
+    let normalizer = Normalization::default().fit(&heightmap.data);
+    let matrix: Vec<Vec<u32>> = heightmap.data
+        .par_chunks(width)
+        .map(|row| {
+            row.iter()
+                // Escalar a rango 0-255 y convertir a u32
+                .map(|&value| (normalizer.apply(value) * 255.0).round() as u32)
                 .collect()
         })
         .collect();
@@ -152,7 +136,7 @@ fn main() {
     //     }
     // }
 
-    // let buffer = matrix2buffer_color(&matrix, &colors, xscale, yscale);
+    // let buffer = matrix2buffer_color(&matrix, &colors, 1, 1);
     let buffer = matrix2buffer_simple(&matrix);
     
 
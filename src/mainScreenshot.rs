@@ -11,33 +11,18 @@ use ::image as img;
 use ::image::ColorType;
 // use iced::widget::image::Handle::Bytes;
 
-use noise::{NoiseFn, Perlin};
+use ruprogen::normalize::Normalization;
+use ruprogen::{generate, NoiseParams};
 
 fn main() -> iced::Result {
     tracing_subscriber::fmt::init();
 
-    iced::application("Screenshot - Iced", Example::update, Example::view)
+    iced::application(Example::default, Example::update, Example::view)
+        .title("Screenshot - Iced")
         .subscription(Example::subscription)
         .run()
 }
 
-fn fractal_noise(perlin: &Perlin, pos: [f64; 2], octaves: u32, lacunarity: f64, persistence: f64) -> f64 {
-    let mut total = 0.0;
-    let mut frequency = 1.0;
-    let mut amplitude = 1.0;
-    let mut maxvalue = 0.0;
-
-    for _ in 0..octaves {
-        total += perlin.get([pos[0] * frequency, pos[1] * frequency]) * amplitude;
-
-        maxvalue += amplitude;
-        amplitude *= persistence;
-        frequency *= lacunarity;
-    }
-
-    total / maxvalue // Normalizamos a -1.0..1.0 (más o menos)
-}
-
 #[derive(Default)]
 struct Example {
     screenshot: Option<(Screenshot, image::Handle)>,
@@ -68,7 +53,7 @@ impl Example {
     fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::Screenshot => {
-                return window::get_latest()
+                return window::latest()
                     .and_then(window::screenshot)
                     .map(Message::Screenshotted);
             }
@@ -78,7 +63,7 @@ impl Example {
                     image::Handle::from_rgba(
                         screenshot.size.width,
                         screenshot.size.height,
-                        screenshot.bytes,
+                        screenshot.rgba,
                     ),
                 ));
             }
@@ -88,20 +73,25 @@ impl Example {
 
                     let width : u32 = 300;
                     let height : u32 = 300;
-                    let size : usize = (width * height * 4).try_into().unwrap();
-                    let perlin = Perlin::new(0);
+                    let heightmap = generate(&NoiseParams {
+                        octaves: 8,
+                        lacunarity: 6.0,
+                        persistence: 0.9,
+                        frequency: 1.0,
+                        amplitude: 1.0,
+                        seed: 0,
+                        width,
+                        height,
+                        ..NoiseParams::default()
+                    });
 
-                    let mut pixels: Vec<u8> = Vec::with_capacity(size);
+                    let mut pixels: Vec<u8> = Vec::with_capacity(heightmap.data.len() * 4);
                     // TODO
                     // unos controles para el tamaño (rehacer los controles, vamos)
-                    for j in 0..(height) {
-                        for i in 0..(width) {
-                            let x = i as f64 / width as f64;
-                            let y = j as f64 / height as f64;
-                            let prev = fractal_noise(&perlin, [x, y], 8, 6.0, 0.9);
-                            let value: u8 = (prev * 255.999) as u8;
-                            pixels.extend_from_slice(&[value, value, value, 255]);
-                        }
+                    let normalizer = Normalization::default().fit(&heightmap.data);
+                    for &prev in &heightmap.data {
+                        let value: u8 = (normalizer.apply(prev) * 255.999) as u8;
+                        pixels.extend_from_slice(&[value, value, value, 255]);
                     }
                     let handle = image::Handle::from_rgba(width, height, pixels);
 
@@ -157,7 +147,7 @@ impl Example {
                                 image::Handle::from_rgba(
                                     crop.size.width,
                                     crop.size.height,
-                                    crop.bytes,
+                                    crop.rgba,
                                 ),
                             ));
                             self.crop_error = None;
@@ -212,7 +202,7 @@ impl Example {
 
         let crop_controls =
             column![crop_origin_controls, crop_dimension_controls]
-                .push_maybe(
+                .push(
                     self.crop_error
                         .as_ref()
                         .map(|error| text!("Crop error! \n{error}")),
@@ -269,7 +259,7 @@ impl Example {
                 .spacing(10)
                 .align_x(Center),
             ]
-            .push_maybe(save_result.map(text))
+            .push(save_result.map(text))
             .spacing(40)
         };
 
@@ -287,12 +277,11 @@ impl Example {
     fn subscription(&self) -> Subscription<Message> {
         use keyboard::key;
 
-        keyboard::on_key_press(|key, _modifiers| {
-            if let keyboard::Key::Named(key::Named::F5) = key {
+        keyboard::listen().filter_map(|event| match event {
+            keyboard::Event::KeyPressed { key: keyboard::Key::Named(key::Named::F5), .. } => {
                 Some(Message::Screenshot)
-            } else {
-                None
             }
+            _ => None,
         })
     }
 }
@@ -303,7 +292,7 @@ async fn save_to_png(screenshot: Screenshot) -> Result<String, PngError> {
     tokio::task::spawn_blocking(move || {
         img::save_buffer(
             &path,
-            &screenshot.bytes,
+            &screenshot.rgba,
             screenshot.size.width,
             screenshot.size.height,
             ColorType::Rgba8,
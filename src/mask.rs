@@ -0,0 +1,258 @@
+//! Máscaras de borde para hacer islas y continentes con un campo sin fin.
+//!
+//! La máscara vale 1 donde se conserva el ruido y 0 donde se hunde; se
+//! combina con el valor pasado a 0..1, como las capas.
+
+use crate::Heightmap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaskShape {
+    /// Elipse que toca los bordes de la imagen.
+    Radial,
+    /// Rectángulo de los bordes de la imagen.
+    Square,
+    /// Distancia radial pasada por `FalloffMask::curve`.
+    Curve,
+    /// Gris de una imagen, estirada al tamaño del heightmap.
+    Image,
+}
+
+impl MaskShape {
+    pub const ALL: [MaskShape; 4] = [MaskShape::Radial, MaskShape::Square, MaskShape::Curve, MaskShape::Image];
+}
+
+impl fmt::Display for MaskShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MaskShape::Radial => "Radial",
+            MaskShape::Square => "Cuadrada",
+            MaskShape::Curve => "Curva propia",
+            MaskShape::Image => "Imagen",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for MaskShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "radial" => Ok(MaskShape::Radial),
+            "square" => Ok(MaskShape::Square),
+            "curve" => Ok(MaskShape::Curve),
+            "image" => Ok(MaskShape::Image),
+            _ => Err(format!("forma de máscara desconocida: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaskMode {
+    /// Escala el valor: el borde llega al mínimo.
+    Multiply,
+    /// Resta lo que le falta a la máscara: el relieve se conserva, más hundido.
+    Subtract,
+}
+
+impl MaskMode {
+    pub const ALL: [MaskMode; 2] = [MaskMode::Multiply, MaskMode::Subtract];
+}
+
+impl fmt::Display for MaskMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MaskMode::Multiply => "Multiplicar",
+            MaskMode::Subtract => "Restar",
+        })
+    }
+}
+
+/// Máscara con sus parámetros. Como en la normalización, los que no usa la
+/// forma elegida se conservan.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FalloffMask {
+    pub enabled: bool,
+    pub shape: MaskShape,
+    pub mode: MaskMode,
+    /// Contraste de la transición: con valores altos el borde es un corte.
+    pub sharpness: f64,
+    /// Desplaza el borde: positivo agranda la zona conservada.
+    pub offset: f64,
+    /// Puntos `[distancia, valor]` en 0..1, del centro al borde, de la forma `Curve`.
+    pub curve: Vec<[f64; 2]>,
+    /// Imagen de la forma `Image`.
+    pub image: String,
+}
+
+impl Default for FalloffMask {
+    fn default() -> Self {
+        FalloffMask {
+            enabled: false,
+            shape: MaskShape::Radial,
+            mode: MaskMode::Multiply,
+            sharpness: 1.0,
+            offset: 0.0,
+            curve: vec![[0.0, 1.0], [0.6, 0.8], [1.0, 0.0]],
+            image: String::new(),
+        }
+    }
+}
+
+impl FalloffMask {
+    /// Aplica la máscara; sólo falla si la imagen de la máscara no se puede leer.
+    pub fn apply(&self, heightmap: &mut Heightmap) -> Result<(), String> {
+        let mask = self.values(heightmap.width, heightmap.height)?;
+        for (value, mask) in heightmap.data.iter_mut().zip(mask) {
+            let t = (*value + 1.0) / 2.0;
+            let t = match self.mode {
+                MaskMode::Multiply => t * mask,
+                MaskMode::Subtract => t - (1.0 - mask),
+            };
+            *value = t * 2.0 - 1.0;
+        }
+        Ok(())
+    }
+
+    /// Añade un punto a la curva en el hueco más grande, sin cambiar su forma.
+    pub fn add_point(&mut self) {
+        let mut curve = self.curve.clone();
+        curve.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        let x = curve.windows(2)
+            .map(|w| (w[0][0], w[1][0]))
+            .max_by(|a, b| (a.1 - a.0).total_cmp(&(b.1 - b.0)))
+            .map_or(0.5, |(a, b)| (a + b) / 2.0);
+        self.curve.push([x, interpolate(&curve, x)]);
+    }
+
+    /// Valor de la máscara en cada pixel de una imagen de `width` x `height`.
+    pub fn values(&self, width: u32, height: u32) -> Result<Vec<f64>, String> {
+        let base: Vec<f64> = match self.shape {
+            MaskShape::Image => {
+                let image = image::open(&self.image)
+                    .map_err(|error| format!("no se pudo leer la máscara {}: {}", self.image, error))?
+                    .to_luma16();
+                image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle)
+                    .into_raw()
+                    .into_iter()
+                    .map(|v| v as f64 / u16::MAX as f64)
+                    .collect()
+            },
+            shape => {
+                let mut curve = self.curve.clone();
+                curve.sort_by(|a, b| a[0].total_cmp(&b[0]));
+                let (w, h) = (width as usize, height as usize);
+                (0..w * h)
+                    .map(|k| {
+                        // Del centro (0) al borde (1) en cada eje
+                        let dx = ((k % w) as f64 + 0.5) / w as f64 * 2.0 - 1.0;
+                        let dy = ((k / w) as f64 + 0.5) / h as f64 * 2.0 - 1.0;
+                        match shape {
+                            MaskShape::Square => smoothstep(1.0 - dx.abs().max(dy.abs())),
+                            MaskShape::Curve => interpolate(&curve, (dx * dx + dy * dy).sqrt()),
+                            MaskShape::Radial | MaskShape::Image => smoothstep(1.0 - (dx * dx + dy * dy).sqrt()),
+                        }
+                    })
+                    .collect()
+            },
+        };
+
+        Ok(base.into_iter()
+            .map(|m| ((m - 0.5) * self.sharpness + 0.5 + self.offset).clamp(0.0, 1.0))
+            .collect())
+    }
+}
+
+fn smoothstep(t: f64) -> f64 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Interpolación lineal entre puntos ordenados; fuera de ellos, el extremo más cercano
+fn interpolate(points: &[[f64; 2]], x: f64) -> f64 {
+    let next = points.partition_point(|point| point[0] <= x);
+    match (next.checked_sub(1).and_then(|i| points.get(i)), points.get(next)) {
+        (Some(a), Some(b)) if b[0] > a[0] => a[1] + (b[1] - a[1]) * (x - a[0]) / (b[0] - a[0]),
+        (Some(a), _) => a[1],
+        (None, Some(b)) => b[1],
+        (None, None) => 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ruprogen-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn radial_is_one_at_centre_and_zero_outside() {
+        let values = FalloffMask::default().values(5, 5).unwrap();
+        assert_eq!(values[2 * 5 + 2], 1.0);
+        // Las esquinas quedan fuera de la elipse
+        for corner in [0, 4, 20, 24] {
+            assert_eq!(values[corner], 0.0);
+        }
+        assert!(values.iter().all(|v| (0.0..=1.0).contains(v)));
+    }
+
+    #[test]
+    fn unreadable_images_are_errors() {
+        let dir = temp_dir("mask");
+        let mask = |image: PathBuf| FalloffMask {
+            shape: MaskShape::Image,
+            image: image.to_string_lossy().into_owned(),
+            ..FalloffMask::default()
+        };
+
+        assert!(mask(dir.join("no-existe.png")).values(8, 8).is_err());
+        let garbage = dir.join("basura.png");
+        fs::write(&garbage, b"no es un png").unwrap();
+        let mut heightmap = Heightmap { width: 4, height: 4, data: vec![0.0; 16] };
+        assert!(mask(garbage).apply(&mut heightmap).is_err());
+        assert_eq!(heightmap.data, [0.0; 16]);
+
+        // De otro tamaño se estira al del heightmap
+        let small = dir.join("pequeña.png");
+        image::GrayImage::from_pixel(3, 2, image::Luma([255])).save(&small).unwrap();
+        let values = mask(small).values(8, 5).unwrap();
+        assert_eq!(values.len(), 40);
+        assert!(values.iter().all(|&v| v == 1.0));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn add_point_keeps_the_curve() {
+        let mut mask = FalloffMask::default();
+        let before = mask.clone();
+        mask.add_point();
+
+        assert_eq!(mask.curve.len(), before.curve.len() + 1);
+        let [x, y] = *mask.curve.last().unwrap();
+        // En el hueco más grande, entre 0 y 0.6, y sobre la curva que había
+        assert_eq!(x, 0.3);
+        assert!((y - 0.9).abs() < 1e-12);
+        assert!(mask.curve.iter().all(|p| (0.0..=1.0).contains(&p[0]) && (0.0..=1.0).contains(&p[1])));
+        let curve = |mask: FalloffMask| FalloffMask { shape: MaskShape::Curve, ..mask }.values(9, 9).unwrap();
+        let (old, new) = (curve(before), curve(mask));
+        assert!(old.iter().zip(&new).all(|(a, b)| (a - b).abs() < 1e-12));
+
+        let mut empty = FalloffMask { curve: Vec::new(), ..FalloffMask::default() };
+        empty.add_point();
+        assert_eq!(empty.curve, [[0.5, 1.0]]);
+    }
+}
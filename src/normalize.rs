@@ -0,0 +1,206 @@
+//! Paso de los valores crudos del ruido a 0..1 antes de aplicar la paleta.
+//!
+//! El fractal sólo devuelve -1..1 «más o menos»: casi nunca llega a los
+//! extremos y a veces se pasa. Cada modo decide cómo repartir esos valores.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NormalizeMode {
+    /// Rango fijo `min..max`; lo que quede fuera se recorta.
+    Fixed,
+    /// Estira el mínimo y el máximo observados a 0..1.
+    MinMax,
+    /// Estira entre dos percentiles, recortando los valores extremos.
+    Percentile,
+    /// Ecualiza el histograma: cada color cubre la misma área de la imagen.
+    Equalize,
+}
+
+impl NormalizeMode {
+    pub const ALL: [NormalizeMode; 4] = [
+        NormalizeMode::Fixed,
+        NormalizeMode::MinMax,
+        NormalizeMode::Percentile,
+        NormalizeMode::Equalize,
+    ];
+}
+
+impl fmt::Display for NormalizeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NormalizeMode::Fixed => "Rango fijo",
+            NormalizeMode::MinMax => "Mínimo y máximo",
+            NormalizeMode::Percentile => "Percentiles",
+            NormalizeMode::Equalize => "Ecualizar histograma",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for NormalizeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fixed" => Ok(NormalizeMode::Fixed),
+            "minmax" => Ok(NormalizeMode::MinMax),
+            "percentile" => Ok(NormalizeMode::Percentile),
+            "equalize" => Ok(NormalizeMode::Equalize),
+            _ => Err(format!("normalización desconocida: {}", s)),
+        }
+    }
+}
+
+/// Modo de normalización con sus parámetros. Los que no usa el modo se
+/// conservan para no perderlos al cambiar de uno a otro.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Normalization {
+    pub mode: NormalizeMode,
+    /// Rango del modo fijo.
+    pub min: f64,
+    pub max: f64,
+    /// Percentiles, en 0..100, del modo de percentiles.
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Normalization { mode: NormalizeMode::MinMax, min: -1.0, max: 1.0, low: 2.0, high: 98.0 }
+    }
+}
+
+impl Normalization {
+    /// Ajusta la normalización a unos datos concretos.
+    pub fn fit(&self, data: &[f64]) -> Normalizer {
+        match self.mode {
+            NormalizeMode::Fixed => Normalizer::Linear { min: self.min, max: self.max },
+            NormalizeMode::MinMax => {
+                let (min, max) = data.iter()
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+                Normalizer::Linear { min, max }
+            },
+            NormalizeMode::Percentile => {
+                let sorted = sorted(data);
+                Normalizer::Linear {
+                    min: percentile(&sorted, self.low.min(self.high)),
+                    max: percentile(&sorted, self.high.max(self.low)),
+                }
+            },
+            NormalizeMode::Equalize => {
+                const QUANTILES: usize = 1024;
+                let sorted = sorted(data);
+                let quantiles = (0..=QUANTILES)
+                    .map(|k| percentile(&sorted, k as f64 * 100.0 / QUANTILES as f64))
+                    .collect();
+                Normalizer::Equalized { quantiles }
+            },
+        }
+    }
+}
+
+/// Normalización ya ajustada a una imagen.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Normalizer {
+    Linear { min: f64, max: f64 },
+    /// Cuantiles equiespaciados de los datos; `apply` interpola entre ellos.
+    Equalized { quantiles: Vec<f64> },
+}
+
+impl Normalizer {
+    /// Valor en 0..1, recortado.
+    pub fn apply(&self, value: f64) -> f64 {
+        match self {
+            Normalizer::Linear { min, max } => {
+                let span = max - min;
+                if span > 0.0 { ((value - min) / span).clamp(0.0, 1.0) } else { 0.5 }
+            },
+            Normalizer::Equalized { quantiles } => {
+                let last = quantiles.len().saturating_sub(1);
+                if last == 0 {
+                    return 0.5;
+                }
+                // Primer cuantil mayor que el valor; se interpola con el anterior
+                let next = quantiles.partition_point(|&q| q <= value);
+                if next == 0 {
+                    return 0.0;
+                }
+                if next > last {
+                    return 1.0;
+                }
+                let (a, b) = (quantiles[next - 1], quantiles[next]);
+                let k = if b > a { (value - a) / (b - a) } else { 0.0 };
+                ((next - 1) as f64 + k) / last as f64
+            },
+        }
+    }
+}
+
+fn sorted(data: &[f64]) -> Vec<f64> {
+    let mut sorted = data.to_vec();
+    sorted.sort_unstable_by(f64::total_cmp);
+    sorted
+}
+
+/// Percentil `p` (0..100) de unos datos ya ordenados, interpolando entre vecinos.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let Some(&first) = sorted.first() else { return 0.0 };
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (i, k) = (rank.floor() as usize, rank.fract());
+    match sorted.get(i + 1) {
+        Some(&next) => sorted[i] + (next - sorted[i]) * k,
+        None => sorted.get(i).copied().unwrap_or(first),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minmax_stretches_to_unit_range() {
+        let normalizer = Normalization::default().fit(&[-0.5, 0.0, 0.25]);
+        assert_eq!(normalizer.apply(-0.5), 0.0);
+        assert_eq!(normalizer.apply(0.25), 1.0);
+        assert!((normalizer.apply(0.0) - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(normalizer.apply(5.0), 1.0);
+    }
+
+    #[test]
+    fn flat_data_maps_to_middle() {
+        let normalizer = Normalization::default().fit(&[0.3; 10]);
+        assert_eq!(normalizer.apply(0.3), 0.5);
+    }
+
+    #[test]
+    fn fixed_ignores_data() {
+        let normalization = Normalization { mode: NormalizeMode::Fixed, min: 0.0, max: 2.0, ..Normalization::default() };
+        let normalizer = normalization.fit(&[100.0, 200.0]);
+        assert_eq!(normalizer.apply(1.0), 0.5);
+    }
+
+    #[test]
+    fn percentile_clips_outliers() {
+        let data: Vec<f64> = (0..=100).map(f64::from).collect();
+        let normalization = Normalization { mode: NormalizeMode::Percentile, low: 10.0, high: 90.0, ..Normalization::default() };
+        let normalizer = normalization.fit(&data);
+        assert_eq!(normalizer.apply(10.0), 0.0);
+        assert_eq!(normalizer.apply(50.0), 0.5);
+        assert_eq!(normalizer.apply(95.0), 1.0);
+    }
+
+    #[test]
+    fn equalize_is_monotonic_and_spreads_values() {
+        // Muy sesgados: casi todos cerca de 0
+        let data: Vec<f64> = (0..1000).map(|k| (k as f64 / 1000.0).powi(4)).collect();
+        let normalizer = Normalization { mode: NormalizeMode::Equalize, ..Normalization::default() }.fit(&data);
+        let values: Vec<f64> = data.iter().map(|&v| normalizer.apply(v)).collect();
+        assert!(values.windows(2).all(|w| w[0] <= w[1]));
+        assert!((values[500] - 0.5).abs() < 0.01);
+    }
+}
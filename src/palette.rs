@@ -0,0 +1,291 @@
+//! Degradados para pasar de valores de ruido a colores.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Color en una posición del degradado, con la posición en 0..1.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorStop {
+    pub position: f64,
+    /// En los presets se escribe como `"#RRGGBB"`.
+    #[serde(with = "hex_color")]
+    pub color: [u8; 3],
+}
+
+pub(crate) mod hex_color {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &[u8; 3], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_hex(*color))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 3], D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        super::parse_hex(&hex).ok_or_else(|| de::Error::custom(format!("color no válido: {}", hex)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GradientMode {
+    /// Interpola entre paradas vecinas.
+    Smooth,
+    /// Cada parada pinta un tramo de color plano hasta la siguiente.
+    Bands,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Gradient {
+    pub stops: Vec<ColorStop>,
+    pub mode: GradientMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientPreset {
+    Grayscale,
+    Terrain,
+    Heat,
+    Ocean,
+    Viridis,
+}
+
+impl GradientPreset {
+    pub const ALL: [GradientPreset; 5] = [
+        GradientPreset::Grayscale,
+        GradientPreset::Terrain,
+        GradientPreset::Heat,
+        GradientPreset::Ocean,
+        GradientPreset::Viridis,
+    ];
+}
+
+impl fmt::Display for GradientPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GradientPreset::Grayscale => "Grises",
+            GradientPreset::Terrain => "Terreno",
+            GradientPreset::Heat => "Calor",
+            GradientPreset::Ocean => "Océano",
+            GradientPreset::Viridis => "Viridis",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for GradientPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "grayscale" => Ok(GradientPreset::Grayscale),
+            "terrain" => Ok(GradientPreset::Terrain),
+            "heat" => Ok(GradientPreset::Heat),
+            "ocean" => Ok(GradientPreset::Ocean),
+            "viridis" => Ok(GradientPreset::Viridis),
+            _ => Err(format!("paleta desconocida: {}", s)),
+        }
+    }
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Gradient::preset(GradientPreset::Grayscale)
+    }
+}
+
+impl Gradient {
+    pub fn preset(preset: GradientPreset) -> Self {
+        let stops: &[(f64, u32)] = match preset {
+            GradientPreset::Grayscale => &[(0.0, 0x000000), (1.0, 0xFFFFFF)],
+            GradientPreset::Terrain => &[
+                (0.0, 0x0B2447),
+                (0.42, 0x2E6FB5),
+                (0.5, 0xE6D8A2),
+                (0.55, 0x5A9A3C),
+                (0.72, 0x2F5D22),
+                (0.85, 0x7A6A58),
+                (1.0, 0xFFFFFF),
+            ],
+            GradientPreset::Heat => &[
+                (0.0, 0x000000),
+                (0.35, 0x9B0000),
+                (0.6, 0xFF6A00),
+                (0.85, 0xFFE600),
+                (1.0, 0xFFFFFF),
+            ],
+            GradientPreset::Ocean => &[
+                (0.0, 0x000814),
+                (0.3, 0x001D3D),
+                (0.6, 0x003566),
+                (0.85, 0x1D7FBF),
+                (1.0, 0x8FD3FF),
+            ],
+            GradientPreset::Viridis => &[
+                (0.0, 0x440154),
+                (0.25, 0x3B528B),
+                (0.5, 0x21918C),
+                (0.75, 0x5EC962),
+                (1.0, 0xFDE725),
+            ],
+        };
+
+        Gradient {
+            stops: stops.iter()
+                .map(|&(position, rgb)| ColorStop {
+                    position,
+                    color: [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8],
+                })
+                .collect(),
+            mode: GradientMode::Smooth,
+        }
+    }
+
+    /// Paradas ordenadas por posición. Se guardan en el orden en que se
+    /// editan para que la interfaz no las reordene bajo el cursor.
+    pub fn sorted_stops(&self) -> Vec<ColorStop> {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        stops
+    }
+
+    /// Color para `t` en 0..1 (se recorta fuera de ese rango).
+    pub fn color_at(&self, t: f64) -> [u8; 3] {
+        color_in(&self.sorted_stops(), self.mode, t)
+    }
+
+    /// Tabla de `size` colores equiespaciados, para colorear sin buscar paradas en cada pixel.
+    pub fn lut(&self, size: usize) -> Vec<[u8; 3]> {
+        let stops = self.sorted_stops();
+        let last = size.max(2) - 1;
+        (0..=last).map(|i| color_in(&stops, self.mode, i as f64 / last as f64)).collect()
+    }
+
+    /// Añade una parada en el centro del hueco más grande, con el color que ya había allí.
+    pub fn add_stop(&mut self) {
+        let stops = self.sorted_stops();
+        let position = match stops.len() {
+            0 => 0.5,
+            1 => if stops[0].position < 0.5 { 1.0 } else { 0.0 },
+            _ => {
+                let (a, b) = stops.windows(2)
+                    .map(|w| (w[0].position, w[1].position))
+                    .max_by(|x, y| (x.1 - x.0).total_cmp(&(y.1 - y.0)))
+                    .unwrap();
+                (a + b) / 2.0
+            }
+        };
+        let color = self.color_at(position);
+        self.stops.push(ColorStop { position, color });
+    }
+}
+
+fn color_in(stops: &[ColorStop], mode: GradientMode, t: f64) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0);
+    let Some(first) = stops.first() else { return [0, 0, 0] };
+
+    // Índice de la primera parada por encima de t
+    let next = stops.partition_point(|stop| stop.position <= t);
+    if next == 0 {
+        return first.color;
+    }
+    let prev = stops[next - 1];
+    if next == stops.len() || mode == GradientMode::Bands {
+        return prev.color;
+    }
+
+    let stop = stops[next];
+    let span = stop.position - prev.position;
+    let k = if span > 0.0 { (t - prev.position) / span } else { 0.0 };
+    std::array::from_fn(|c| {
+        (prev.color[c] as f64 + (stop.color[c] as f64 - prev.color[c] as f64) * k).round() as u8
+    })
+}
+
+/// Lee colores escritos como `#RRGGBB` o `RRGGBB`.
+pub fn parse_hex(s: &str) -> Option<[u8; 3]> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+pub fn to_hex(color: [u8; 3]) -> String {
+    format!("#{:02X}{:02X}{:02X}", color[0], color[1], color[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(stops: &[(f64, [u8; 3])], mode: GradientMode) -> Gradient {
+        Gradient { stops: stops.iter().map(|&(position, color)| ColorStop { position, color }).collect(), mode }
+    }
+
+    #[test]
+    fn color_at_interpolates_and_clamps() {
+        let g = gradient(&[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])], GradientMode::Smooth);
+        assert_eq!(g.color_at(0.0), [0, 0, 0]);
+        assert_eq!(g.color_at(0.5), [128, 128, 128]);
+        assert_eq!(g.color_at(1.0), [255, 255, 255]);
+        assert_eq!(g.color_at(-3.0), [0, 0, 0]);
+        assert_eq!(g.color_at(3.0), [255, 255, 255]);
+
+        let bands = Gradient { mode: GradientMode::Bands, ..g };
+        assert_eq!(bands.color_at(0.99), [0, 0, 0]);
+        assert_eq!(bands.color_at(1.0), [255, 255, 255]);
+    }
+
+    #[test]
+    fn unsorted_stops_are_sorted_first() {
+        let g = gradient(&[(1.0, [0, 0, 255]), (0.0, [255, 0, 0]), (0.5, [0, 255, 0])], GradientMode::Smooth);
+        assert_eq!(g.color_at(0.25), [128, 128, 0]);
+        assert_eq!(g.color_at(0.75), [0, 128, 128]);
+        // Fuera de las paradas, el color del extremo
+        let inner = gradient(&[(0.8, [9, 9, 9]), (0.2, [1, 1, 1])], GradientMode::Smooth);
+        assert_eq!(inner.color_at(0.0), [1, 1, 1]);
+        assert_eq!(inner.color_at(1.0), [9, 9, 9]);
+    }
+
+    #[test]
+    fn duplicated_stops_make_a_hard_edge() {
+        let g = gradient(&[(0.0, [200, 0, 0]), (0.5, [0, 0, 0]), (0.5, [255, 255, 255]), (1.0, [55, 55, 255])],
+                         GradientMode::Smooth);
+        assert_eq!(g.color_at(0.25), [100, 0, 0]);
+        assert_eq!(g.color_at(0.5), [255, 255, 255]);
+        assert_eq!(g.color_at(0.75), [155, 155, 255]);
+    }
+
+    #[test]
+    fn lut_matches_color_at() {
+        let g = Gradient::preset(GradientPreset::Terrain);
+        let lut = g.lut(256);
+        assert_eq!(lut.len(), 256);
+        for (i, color) in lut.iter().enumerate() {
+            assert_eq!(*color, g.color_at(i as f64 / 255.0));
+        }
+        // Siempre al menos los dos extremos
+        assert_eq!(g.lut(0), [g.color_at(0.0), g.color_at(1.0)]);
+        assert_eq!(Gradient { stops: Vec::new(), ..g }.lut(4), [[0, 0, 0]; 4]);
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        for color in [[0, 0, 0], [255, 255, 255], [18, 52, 86], [171, 205, 239]] {
+            assert_eq!(parse_hex(&to_hex(color)), Some(color));
+        }
+        assert_eq!(to_hex([171, 205, 239]), "#ABCDEF");
+        assert_eq!(parse_hex(" abcdef "), Some([171, 205, 239]));
+        assert_eq!(parse_hex("#aBcDeF"), Some([171, 205, 239]));
+    }
+
+    #[test]
+    fn bad_hex_is_rejected() {
+        for bad in ["", "#", "#12345", "#1234567", "#12345G", "#xyzxyz", "#ééé", "+12345", "0x1234"] {
+            assert_eq!(parse_hex(bad), None, "{:?}", bad);
+        }
+    }
+}
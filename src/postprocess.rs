@@ -0,0 +1,60 @@
+//! Pasos que se aplican al heightmap después de muestrear el ruido.
+//!
+//! No forman parte de `NoiseParams` porque no se pueden calcular pixel a
+//! pixel: `sample_at` sigue dando el valor del ruido sin procesar.
+
+use crate::erosion::{HydraulicErosion, ThermalErosion};
+use crate::mask::FalloffMask;
+use crate::Heightmap;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::AtomicBool;
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcess {
+    pub mask: FalloffMask,
+    pub hydraulic: HydraulicErosion,
+    pub thermal: ThermalErosion,
+}
+
+impl PostProcess {
+    /// Si algún paso está activado.
+    pub fn is_active(&self) -> bool {
+        self.mask.enabled || self.hydraulic.enabled || self.thermal.enabled
+    }
+
+    /// Los mismos pasos para una imagen `factor` veces más pequeña en cada eje.
+    pub fn scaled(&self, factor: f64) -> PostProcess {
+        PostProcess {
+            mask: self.mask.clone(),
+            hydraulic: self.hydraulic.scaled(factor),
+            thermal: self.thermal.scaled(factor),
+        }
+    }
+
+    /// Aplica los pasos activados en orden: la máscara, para que la erosión
+    /// trabaje ya sobre la isla, la erosión hidráulica y la térmica, que
+    /// suaviza los cortados que deja. `seed` fija lo que tengan de aleatorio.
+    /// Devuelve `Ok(false)` si se cancela.
+    pub fn apply(&self, heightmap: &mut Heightmap, seed: u32, cancel: &AtomicBool, progress: &dyn Fn(f32))
+        -> Result<bool, String> {
+        if self.mask.enabled {
+            self.mask.apply(heightmap)?;
+        }
+
+        let steps = [self.hydraulic.enabled, self.thermal.enabled].iter().filter(|&&enabled| enabled).count() as f32;
+        // Cada paso ocupa una parte igual de la barra de progreso
+        let mut done = 0.0;
+
+        if self.hydraulic.enabled {
+            if !self.hydraulic.apply(heightmap, seed, cancel, &|fraction| progress((done + fraction) / steps)) {
+                return Ok(false);
+            }
+            done += 1.0;
+        }
+        if self.thermal.enabled && !self.thermal.apply(heightmap, cancel, &|fraction| progress((done + fraction) / steps)) {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
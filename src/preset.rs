@@ -0,0 +1,168 @@
+//! Presets con nombre: todo lo necesario para repetir una imagen, en TOML.
+//!
+//! Se guardan en `presets/`, uno por fichero, para poder compartirlos en el
+//! repositorio y editarlos a mano.
+
+use crate::biome::BiomeSettings;
+use crate::graph::Graph;
+use crate::normalize::Normalization;
+use crate::palette::Gradient;
+use crate::postprocess::PostProcess;
+use crate::rivers::RiverSettings;
+use crate::NoiseParams;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Carpeta por defecto de los presets, relativa al directorio de trabajo.
+pub const PRESET_DIR: &str = "presets";
+
+/// Fichero (dentro de `PRESET_DIR`) con el nombre del último preset usado.
+/// Es local a cada máquina y no se comparte.
+const LAST_USED_FILE: &str = ".ultimo";
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub name: String,
+    pub params: NoiseParams,
+    pub gradient: Gradient,
+    pub normalize: Normalization,
+    pub post: PostProcess,
+    pub biomes: BiomeSettings,
+    pub rivers: RiverSettings,
+    /// Grafo de nodos, si se ha montado alguno en el editor.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub graph: Option<Graph>,
+}
+
+impl Preset {
+    pub fn load(path: impl AsRef<Path>) -> Result<Preset, String> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("no se pudo leer {}: {}", path.display(), error))?;
+        let mut preset: Preset = toml::from_str(&contents)
+            .map_err(|error| format!("preset {} no válido: {}", path.display(), error))?;
+        if preset.name.is_empty() {
+            preset.name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        }
+        Ok(preset)
+    }
+
+    /// Guarda el preset en `dir` con un nombre de fichero sacado de su nombre
+    /// y devuelve la ruta. Nombres distintos pueden dar el mismo fichero
+    /// ("Montes" y "montes!"): sólo se sobrescribe si es del mismo preset.
+    pub fn save(&self, dir: impl AsRef<Path>) -> Result<PathBuf, String> {
+        let dir = dir.as_ref();
+        let path = dir.join(file_name(&self.name)?);
+        if path.exists() {
+            let existing = Preset::load(&path)?;
+            if existing.name != self.name.trim() {
+                return Err(format!("{} ya es el preset {:?}; elige otro nombre", path.display(), existing.name));
+            }
+        }
+        let contents = toml::to_string_pretty(self)
+            .map_err(|error| format!("no se pudo serializar el preset: {}", error))?;
+        fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&path, contents))
+            .map_err(|error| format!("no se pudo guardar {}: {}", path.display(), error))?;
+        Ok(path)
+    }
+}
+
+/// Nombre de fichero para un preset: minúsculas, guiones en lugar de
+/// espacios y sin nada que pueda salirse de la carpeta.
+pub fn file_name(name: &str) -> Result<String, String> {
+    let stem: String = name.trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    let stem = stem.trim_matches('-');
+    if stem.is_empty() {
+        return Err(format!("nombre de preset no válido: {:?}", name));
+    }
+    Ok(format!("{}.toml", stem))
+}
+
+/// Presets de `dir` ordenados por nombre. Los ficheros que no se pueden leer
+/// se devuelven como error junto al resto en lugar de abortar la lista.
+pub fn list(dir: impl AsRef<Path>) -> Vec<(PathBuf, Result<Preset, String>)> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+
+    let mut presets: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .map(|path| {
+            let preset = Preset::load(&path);
+            (path, preset)
+        })
+        .collect();
+    presets.sort_by(|a, b| a.0.cmp(&b.0));
+    presets
+}
+
+/// Ruta del último preset usado, si sigue existiendo.
+pub fn last_used(dir: impl AsRef<Path>) -> Option<PathBuf> {
+    let dir = dir.as_ref();
+    let name = fs::read_to_string(dir.join(LAST_USED_FILE)).ok()?;
+    let path = dir.join(name.trim());
+    path.is_file().then_some(path)
+}
+
+pub fn set_last_used(dir: impl AsRef<Path>, path: &Path) -> Result<(), String> {
+    let dir = dir.as_ref();
+    let name = path.file_name().ok_or_else(|| format!("ruta de preset no válida: {}", path.display()))?;
+    fs::write(dir.join(LAST_USED_FILE), name.to_string_lossy().as_bytes())
+        .map_err(|error| format!("no se pudo recordar el último preset: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::GradientPreset;
+
+    // Carpeta vacía y propia de cada prueba
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ruprogen-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn file_name_is_sanitized() {
+        assert_eq!(file_name("Montes altos").unwrap(), "montes-altos.toml");
+        assert_eq!(file_name("../fuera").unwrap(), "fuera.toml");
+        assert!(file_name("  !!  ").is_err());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = temp_dir("round-trip");
+        let mut preset = Preset { name: String::from("Montes"), ..Preset::default() };
+        preset.params.seed = 42;
+        preset.params.octaves = 5;
+        preset.gradient = Gradient::preset(GradientPreset::Terrain);
+        preset.post.thermal.enabled = true;
+        preset.biomes.enabled = true;
+        preset.rivers.enabled = true;
+        preset.graph = Some(Graph::starter(&preset.params));
+
+        let path = preset.save(&dir).unwrap();
+        assert_eq!(Preset::load(&path).unwrap(), preset);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_refuses_to_overwrite_another_preset() {
+        let dir = temp_dir("collision");
+        let first = Preset { name: String::from("Montes"), ..Preset::default() };
+        let path = first.save(&dir).unwrap();
+
+        let other = Preset { name: String::from("montes!"), ..Preset::default() };
+        assert!(other.save(&dir).is_err());
+        assert_eq!(Preset::load(&path).unwrap().name, "Montes");
+        // El mismo preset sí se puede volver a guardar
+        assert!(first.save(&dir).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
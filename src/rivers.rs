@@ -0,0 +1,355 @@
+//! Red de ríos que sigue el agua por el terreno.
+//!
+//! Primero se rellenan las depresiones (priority-flood) para que todo desagüe
+//! al mar o al borde de la imagen; después cada pixel vierte en su vecino más
+//! bajo de los ocho (D8) y se cuenta cuánta superficie pasa por cada uno. Donde
+//! pasa más de `threshold` hay río. Las coordenadas de los ríos son pixels del
+//! heightmap, con el centro del pixel en `x + 0.5`.
+
+use crate::Heightmap;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiverSettings {
+    pub enabled: bool,
+    /// Fracción de la imagen que tiene que desaguar por un pixel para que haya río.
+    pub threshold: f64,
+    /// Altura, de 0 (el mínimo) a 1 (el máximo), por debajo de la cual es mar.
+    /// Por defecto coincide con el nivel del agua de los biomas.
+    pub sea_level: f64,
+    /// Si se hunden los cauces en el heightmap o sólo se calculan.
+    pub carve: bool,
+    /// Lo que se hunde el cauce del río más caudaloso, en valor crudo.
+    pub depth: f64,
+}
+
+impl Default for RiverSettings {
+    fn default() -> Self {
+        RiverSettings { enabled: false, threshold: 0.002, sea_level: 0.4, carve: true, depth: 0.05 }
+    }
+}
+
+/// Un tramo de río, de su nacimiento a la desembocadura o al río en el que entra.
+#[derive(Debug, Clone, PartialEq)]
+pub struct River {
+    pub points: Vec<[f64; 2]>,
+    /// Fracción de la imagen que desagua por el último punto.
+    pub flow: f64,
+}
+
+/// Curva de Bézier cuadrática, con la misma forma que `Curve` en `mainBezier.rs`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Curve {
+    pub from: [f64; 2],
+    pub to: [f64; 2],
+    pub control: [f64; 2],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiverNetwork {
+    pub width: u32,
+    pub height: u32,
+    /// Los más caudalosos primero.
+    pub rivers: Vec<River>,
+}
+
+// Vecinos en el mismo orden que las direcciones D8
+const NEIGHBOURS: [(i64, i64); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+// Lo mínimo que sube cada pixel relleno sobre el que desagua, para que no haya llanos
+const EPSILON: f64 = 1e-9;
+
+// Altura para la cola de prioridad; los NaN no llegan aquí
+#[derive(PartialEq)]
+struct Level(f64, usize);
+
+impl Eq for Level {}
+
+impl PartialOrd for Level {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Level {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+impl RiverSettings {
+    /// Calcula los ríos y, con `carve`, hunde sus cauces en el heightmap.
+    pub fn apply(&self, heightmap: &mut Heightmap) -> RiverNetwork {
+        let map = &heightmap.data;
+        let (min, max) = map.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let sea = min + (max - min) * self.sea_level.clamp(0.0, 1.0);
+        let Drainage { filled, downstream, accumulation } = drainage(heightmap, sea);
+
+        let total = map.len().max(1) as f64;
+        let minimum = (self.threshold * total).max(2.0);
+        let is_river = |index: usize| accumulation[index] >= minimum && map[index] > sea;
+        let rivers = trace(&downstream, &accumulation, &is_river, heightmap.width as usize, total);
+
+        if self.carve {
+            let channels: Vec<usize> = (0..map.len()).filter(|&index| is_river(index)).collect();
+            let peak = rivers.iter().map(|river| river.flow * total).fold(minimum, f64::max);
+            for index in channels {
+                // Los ríos más pequeños se hunden menos, en escala logarítmica
+                let depth = self.depth * (1.0 + (accumulation[index] / minimum).ln()) / (1.0 + (peak / minimum).ln());
+                heightmap.data[index] = heightmap.data[index].min(filled[index] - depth);
+            }
+        }
+
+        RiverNetwork { width: heightmap.width, height: heightmap.height, rivers }
+    }
+}
+
+// Cómo corre el agua por un heightmap
+struct Drainage {
+    filled: Vec<f64>, // sin depresiones: cada pixel por encima del que desagua
+    downstream: Vec<Option<usize>>, // `None` en el mar y el borde
+    accumulation: Vec<f64>, // pixels que pasan por cada uno, contándose a sí mismo
+}
+
+fn drainage(heightmap: &Heightmap, sea: f64) -> Drainage {
+    let (width, height) = (heightmap.width as usize, heightmap.height as usize);
+    let map = &heightmap.data;
+    let neighbour = |index: usize, (dx, dy): (i64, i64)| {
+        let (x, y) = ((index % width) as i64 + dx, (index / width) as i64 + dy);
+        (x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height).then(|| y as usize * width + x as usize)
+    };
+
+    // Relleno: se inunda desde el mar y el borde hacia dentro, siempre por el
+    // punto más bajo, y cada pixel queda un poco por encima del que lo alcanza
+    let mut filled = map.clone();
+    let mut queued = vec![false; map.len()];
+    let mut queue = BinaryHeap::new();
+    for index in 0..map.len() {
+        let (x, y) = (index % width, index / width);
+        if map[index] <= sea || x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+            queued[index] = true;
+            queue.push(Reverse(Level(map[index], index)));
+        }
+    }
+    // Orden en que salen de la cola: cada pixel después de aquel en el que desagua
+    let mut order = Vec::with_capacity(map.len());
+    while let Some(Reverse(Level(level, index))) = queue.pop() {
+        order.push(index);
+        for offset in NEIGHBOURS {
+            if let Some(next) = neighbour(index, offset).filter(|&next| !queued[next]) {
+                queued[next] = true;
+                filled[next] = filled[next].max(level + EPSILON);
+                queue.push(Reverse(Level(filled[next], next)));
+            }
+        }
+    }
+
+    // D8: cada pixel que no es mar ni borde vierte en su vecino de mayor pendiente
+    let outlet = |index: usize| map[index] <= sea || NEIGHBOURS.iter().any(|&offset| neighbour(index, offset).is_none());
+    let downstream: Vec<Option<usize>> = (0..map.len())
+        .map(|index| {
+            if outlet(index) {
+                return None;
+            }
+            NEIGHBOURS.iter()
+                .filter_map(|&(dx, dy)| {
+                    let next = neighbour(index, (dx, dy))?;
+                    let drop = (filled[index] - filled[next]) / ((dx * dx + dy * dy) as f64).sqrt();
+                    (drop > 0.0).then_some((next, drop))
+                })
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(next, _)| next)
+        })
+        .collect();
+
+    // Cada pixel suma su superficie y la de lo que le llega al de abajo
+    let mut accumulation = vec![1.0; map.len()];
+    for &index in order.iter().rev() {
+        if let Some(next) = downstream[index] {
+            accumulation[next] += accumulation[index];
+        }
+    }
+
+    Drainage { filled, downstream, accumulation }
+}
+
+// Recorre los ríos desde su desembocadura hacia arriba: el cauce principal
+// sigue al afluente más caudaloso y los demás empiezan un tramo nuevo
+fn trace(downstream: &[Option<usize>], accumulation: &[f64], is_river: &dyn Fn(usize) -> bool, width: usize, total: f64)
+    -> Vec<River> {
+    let mut upstream: Vec<Vec<usize>> = vec![Vec::new(); downstream.len()];
+    for (index, next) in downstream.iter().enumerate() {
+        if let Some(next) = next.filter(|_| is_river(index)) {
+            upstream[next].push(index);
+        }
+    }
+    let center = |index: usize| [(index % width) as f64 + 0.5, (index / width) as f64 + 0.5];
+
+    // Tramos pendientes: el pixel en el que desaguan, si no es el borde, y el
+    // último del tramo
+    let mut pending: Vec<(Option<usize>, usize)> = (0..downstream.len())
+        .filter(|&index| is_river(index) && downstream[index].is_none_or(|next| !is_river(next)))
+        .map(|index| (downstream[index], index))
+        .collect();
+
+    let mut rivers = Vec::new();
+    while let Some((mouth, last)) = pending.pop() {
+        let mut points: Vec<[f64; 2]> = mouth.map(center).into_iter().collect();
+        let mut current = last;
+        loop {
+            points.push(center(current));
+            let mut sources = upstream[current].clone();
+            sources.sort_by(|&a, &b| accumulation[b].total_cmp(&accumulation[a]));
+            let Some((&main, others)) = sources.split_first() else { break };
+            pending.extend(others.iter().map(|&other| (Some(current), other)));
+            current = main;
+        }
+        if points.len() > 2 {
+            points.reverse();
+            rivers.push(River { points, flow: accumulation[last] / total });
+        }
+    }
+
+    rivers.sort_by(|a, b| b.flow.total_cmp(&a.flow));
+    rivers
+}
+
+impl River {
+    /// El tramo suavizado como curvas cuadráticas: cada una va del punto medio
+    /// de un segmento al del siguiente, con el vértice como control.
+    pub fn curves(&self) -> Vec<Curve> {
+        let points = &self.points;
+        let midpoint = |a: [f64; 2], b: [f64; 2]| [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0];
+        let count = points.len();
+        if count < 3 {
+            return points.windows(2).map(|w| Curve { from: w[0], to: w[1], control: midpoint(w[0], w[1]) }).collect();
+        }
+
+        (1..count - 1)
+            .map(|i| Curve {
+                from: if i == 1 { points[0] } else { midpoint(points[i - 1], points[i]) },
+                to: if i == count - 2 { points[count - 1] } else { midpoint(points[i], points[i + 1]) },
+                control: points[i],
+            })
+            .collect()
+    }
+}
+
+impl RiverNetwork {
+    /// Todas las curvas de la red, río a río.
+    pub fn curves(&self) -> Vec<Curve> {
+        self.rivers.iter().flat_map(River::curves).collect()
+    }
+
+    /// La red como SVG del tamaño del heightmap, con el grosor según el caudal.
+    pub fn to_svg(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+            w = self.width,
+            h = self.height,
+        );
+        svg.push_str("<g fill=\"none\" stroke=\"#3070d0\" stroke-linecap=\"round\" stroke-linejoin=\"round\">\n");
+        for river in &self.rivers {
+            let mut path = String::new();
+            for (k, curve) in river.curves().iter().enumerate() {
+                if k == 0 {
+                    let _ = write!(path, "M{:.2} {:.2}", curve.from[0], curve.from[1]);
+                }
+                let _ = write!(path, " Q{:.2} {:.2} {:.2} {:.2}", curve.control[0], curve.control[1], curve.to[0], curve.to[1]);
+            }
+            let _ = writeln!(svg, "<path d=\"{}\" stroke-width=\"{:.2}\"/>", path, self.stroke_width(river));
+        }
+        svg.push_str("</g>\n</svg>\n");
+        svg
+    }
+
+    pub fn save_svg(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        fs::write(path, self.to_svg()).map_err(|error| format!("no se pudo guardar {}: {}", path.display(), error))
+    }
+
+    /// Grosor en pixels del heightmap con que se dibuja un río.
+    pub fn stroke_width(&self, river: &River) -> f64 {
+        let pixels = river.flow * (self.width as f64 * self.height as f64);
+        (pixels.sqrt() / 20.0).clamp(0.5, 4.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cuenco con un hoyo en medio y una pendiente hacia el borde izquierdo
+    fn basin(width: u32, height: u32) -> Heightmap {
+        let data = (0..width * height)
+            .map(|k| {
+                let (x, y) = ((k % width) as f64, (k / width) as f64);
+                let pit = ((x - 20.0).powi(2) + (y - 16.0).powi(2)).sqrt() < 4.0;
+                if pit { -0.5 } else { x / width as f64 + 0.1 * (y / height as f64 - 0.5).abs() }
+            })
+            .collect();
+        Heightmap { width, height, data }
+    }
+
+    #[test]
+    fn every_river_flows_downhill_to_an_outlet() {
+        let settings = RiverSettings { enabled: true, threshold: 0.01, sea_level: 0.0, carve: false, ..RiverSettings::default() };
+        let mut heightmap = basin(48, 32);
+        let original = heightmap.clone();
+        let network = settings.apply(&mut heightmap);
+
+        assert_eq!(heightmap, original, "sin carve no se toca el heightmap");
+        assert!(!network.rivers.is_empty());
+        // Los ríos que no entran en otro terminan en el borde: el hoyo no los atrapa
+        let main = &network.rivers[0];
+        let mouth = main.points.last().unwrap();
+        assert!(mouth[0] < 1.0 || mouth[1] < 1.0 || mouth[0] > 47.0 || mouth[1] > 31.0, "{:?}", mouth);
+        assert!(network.rivers.windows(2).all(|w| w[0].flow >= w[1].flow));
+    }
+
+    #[test]
+    fn filled_terrain_drains_everywhere() {
+        let heightmap = basin(48, 32);
+        let Drainage { filled, downstream, accumulation } = drainage(&heightmap, -1.0);
+        let outlets: Vec<usize> = (0..downstream.len()).filter(|&index| downstream[index].is_none()).collect();
+
+        for start in 0..downstream.len() {
+            // Siempre cuesta abajo en el relleno, hasta una salida
+            let mut index = start;
+            while let Some(next) = downstream[index] {
+                assert!(filled[next] < filled[index]);
+                index = next;
+            }
+            assert!(outlets.contains(&index));
+        }
+        // Toda la superficie llega a alguna salida, también la del hoyo
+        let drained: f64 = outlets.iter().map(|&index| accumulation[index]).sum();
+        assert_eq!(drained, downstream.len() as f64);
+        assert!(filled.iter().zip(&heightmap.data).all(|(filled, original)| filled >= original));
+    }
+
+    #[test]
+    fn carving_only_lowers() {
+        let settings = RiverSettings { enabled: true, threshold: 0.01, sea_level: 0.0, ..RiverSettings::default() };
+        let mut heightmap = basin(48, 32);
+        let original = heightmap.clone();
+        settings.apply(&mut heightmap);
+
+        assert!(heightmap.data.iter().zip(&original.data).all(|(carved, before)| carved <= before));
+        assert!(heightmap.data.iter().zip(&original.data).any(|(carved, before)| carved < before));
+    }
+
+    #[test]
+    fn curves_join_end_to_end() {
+        let river = River { points: vec![[0.0, 0.0], [1.0, 0.0], [2.0, 1.0], [3.0, 1.0]], flow: 0.1 };
+        let curves = river.curves();
+        assert_eq!(curves.first().unwrap().from, [0.0, 0.0]);
+        assert_eq!(curves.last().unwrap().to, [3.0, 1.0]);
+        assert!(curves.windows(2).all(|w| w[0].to == w[1].from));
+    }
+}
@@ -0,0 +1,96 @@
+//! Estadísticas de un campo de ruido, para ajustar proporciones sin ir a ojo.
+
+/// Resumen de los valores crudos de un heightmap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std_dev: f64,
+    sorted: Vec<f64>, // para contar por encima de un umbral sin recorrerlo todo
+}
+
+impl FieldStats {
+    pub fn new(data: &[f64]) -> Self {
+        let mut sorted = data.to_vec();
+        sorted.sort_unstable_by(f64::total_cmp);
+
+        let count = sorted.len().max(1) as f64;
+        let mean = sorted.iter().sum::<f64>() / count;
+        let variance = sorted.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / count;
+
+        FieldStats {
+            min: sorted.first().copied().unwrap_or(0.0),
+            max: sorted.last().copied().unwrap_or(0.0),
+            mean,
+            std_dev: variance.sqrt(),
+            sorted,
+        }
+    }
+
+    /// Fracción (0..1) de valores estrictamente mayores que `threshold`.
+    pub fn fraction_above(&self, threshold: f64) -> f64 {
+        if self.sorted.is_empty() {
+            return 0.0;
+        }
+        let below = self.sorted.partition_point(|&v| v <= threshold);
+        (self.sorted.len() - below) as f64 / self.sorted.len() as f64
+    }
+
+    /// Cuenta de valores en `bins` intervalos iguales entre `min` y `max`.
+    pub fn histogram(&self, bins: usize) -> Vec<u32> {
+        let mut counts = vec![0; bins];
+        let span = self.max - self.min;
+        if bins == 0 {
+            return counts;
+        }
+        for &v in &self.sorted {
+            let bin = if span > 0.0 { ((v - self.min) / span * bins as f64) as usize } else { 0 };
+            counts[bin.min(bins - 1)] += 1;
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_field_has_no_spread() {
+        let stats = FieldStats::new(&[0.25; 50]);
+        assert_eq!((stats.min, stats.max, stats.mean, stats.std_dev), (0.25, 0.25, 0.25, 0.0));
+        // Rango de ancho cero: todo al primer intervalo, sin NaN
+        assert_eq!(stats.histogram(8), [50, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(stats.fraction_above(0.25), 0.0);
+
+        let empty = FieldStats::new(&[]);
+        assert_eq!((empty.mean, empty.std_dev), (0.0, 0.0));
+        assert_eq!(empty.histogram(4), [0; 4]);
+        assert_eq!(empty.fraction_above(0.0), 0.0);
+    }
+
+    #[test]
+    fn histogram_counts_every_value() {
+        let data: Vec<f64> = (0..1000).map(|k| ((k * 37) % 1000) as f64 / 500.0 - 1.0).collect();
+        let stats = FieldStats::new(&data);
+        for bins in [1, 7, 128] {
+            assert_eq!(stats.histogram(bins).iter().sum::<u32>() as usize, data.len());
+        }
+        assert!(stats.histogram(0).is_empty());
+    }
+
+    #[test]
+    fn max_lands_in_last_bin() {
+        let stats = FieldStats::new(&[-1.0, 0.0, 1.0]);
+        assert_eq!(stats.histogram(4), [1, 0, 1, 1]);
+    }
+
+    #[test]
+    fn fraction_above_extremes() {
+        let stats = FieldStats::new(&[-1.0, -0.5, 0.0, 0.5, 1.0]);
+        assert_eq!(stats.fraction_above(stats.min), 0.8);
+        assert_eq!(stats.fraction_above(stats.max), 0.0);
+        assert_eq!(stats.fraction_above(stats.min - 1.0), 1.0);
+    }
+}